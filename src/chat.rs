@@ -1,6 +1,251 @@
+use crate::config::Config;
 use crate::io;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
 
 const COLOR_CODE: char = '&';
+const COLORS: [(&str, char); 16] =
+[
+	("black", '0'), ("navy", '1'), ("green", '2'), ("teal", '3'),
+	("maroon", '4'), ("purple", '5'), ("gold", '6'), ("silver", '7'),
+	("gray", '8'), ("blue", '9'), ("lime", 'a'), ("aqua", 'b'),
+	("red", 'c'), ("pink", 'd'), ("yellow", 'e'), ("white", 'f')
+];
+
+// takes either a colour name, a colour code like &c, or just the c
+pub fn parse_color(color: &str) -> Option<char>
+{
+	let code = color.strip_prefix(COLOR_CODE).unwrap_or(color);
+	let mut chars = code.chars();
+	if let (Some(c), None) = (chars.next(), chars.next())
+	{
+		if c.is_ascii_hexdigit()
+		{
+			return Some(c.to_ascii_lowercase());
+		}
+	}
+	COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(color)).map(|(_, c)| *c)
+}
+pub fn color_names() -> String
+{
+	COLORS.iter().map(|(name, c)| format!("{}{}{}", COLOR_CODE, c, name)).collect::<Vec<_>>().join(" ")
+}
+
+// text goes through a list of passes, one after the other, before it gets wrapped and sent
+pub type Pass<'a> = &'a dyn Fn(&str) -> String;
+pub fn format(text: &str, passes: &[Pass]) -> String
+{
+	passes.iter().fold(text.to_string(), |text, pass| pass(&text))
+}
+
+// %c works the same as &c, for clients where & is awkward to type
+pub fn percent_codes(text: &str) -> String
+{
+	let mut output = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next()
+	{
+		if c == '%' && chars.peek().is_some_and(|c| c.is_ascii_hexdigit())
+		{
+			output.push(COLOR_CODE);
+		}
+		else
+		{
+			output.push(c);
+		}
+	}
+	output
+}
+pub fn strip_colors(text: &str) -> String
+{
+	let mut output = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next()
+	{
+		if c == COLOR_CODE && chars.peek().is_some_and(|c| c.is_ascii_hexdigit())
+		{
+			chars.next();
+		}
+		else
+		{
+			output.push(c);
+		}
+	}
+	output
+}
+
+// the glyphs at the bottom of code page 437, e.g. (darksmile) becomes ☻
+const EMOTES: [(&str, char); 31] =
+[
+	("smile", '☺'), ("darksmile", '☻'), ("heart", '♥'), ("diamond", '♦'), ("club", '♣'), ("spade", '♠'),
+	("*", '•'), ("inversebullet", '◘'), ("circle", '○'), ("inversecircle", '◙'), ("male", '♂'), ("female", '♀'),
+	("note", '♪'), ("notes", '♫'), ("sun", '☼'), (">>", '►'), ("<<", '◄'), ("updown", '↕'), ("!!", '‼'),
+	("p", '¶'), ("s", '§'), ("-", '▬'), ("updownbar", '↨'), ("up", '↑'), ("down", '↓'), ("right", '→'),
+	("left", '←'), ("l", '∟'), ("<>", '↔'), ("^", '▲'), ("v", '▼')
+];
+pub fn emotes(text: &str) -> String
+{
+	let mut output = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('(')
+	{
+		output.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let emote = rest[1..].find(')').and_then(|end| EMOTES.iter().find(|(name, _)| name.eq_ignore_ascii_case(&rest[1..end + 1])).map(|(name, c)| (name.len(), *c)));
+		if let Some((len, c)) = emote
+		{
+			output.push(c);
+			rest = &rest[len + 2..];
+		}
+		else
+		{
+			output.push('(');
+			rest = &rest[1..];
+		}
+	}
+	output.push_str(rest);
+	output
+}
+
+// replaces $name, $world and the like with their value, unknown tokens are left alone
+pub fn tokens(text: &str, values: &[(&str, String)]) -> String
+{
+	let mut output = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('$')
+	{
+		output.push_str(&rest[..start]);
+		rest = &rest[start + 1..];
+		let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+		match values.iter().find(|(token, _)| *token == &rest[..len])
+		{
+			Some((_, value)) =>
+			{
+				output.push_str(value);
+				rest = &rest[len..];
+			}
+			None => output.push('$')
+		}
+	}
+	output.push_str(rest);
+	output
+}
+
+// where on the screen a message shows up, for clients with MessageTypes
+#[derive(Clone, Copy, PartialEq)]
+pub enum MessageType
+{
+	Chat,
+	Status1,
+	Status2,
+	Status3,
+	BottomRight1,
+	BottomRight2,
+	BottomRight3,
+	Announcement
+}
+impl MessageType
+{
+	pub const ALL: [MessageType; 8] = [MessageType::Chat, MessageType::Status1, MessageType::Status2, MessageType::Status3, MessageType::BottomRight1, MessageType::BottomRight2, MessageType::BottomRight3, MessageType::Announcement];
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			MessageType::Chat => "chat",
+			MessageType::Status1 => "status1",
+			MessageType::Status2 => "status2",
+			MessageType::Status3 => "status3",
+			MessageType::BottomRight1 => "bottom1",
+			MessageType::BottomRight2 => "bottom2",
+			MessageType::BottomRight3 => "bottom3",
+			MessageType::Announcement => "center"
+		}
+	}
+	pub fn from_name(name: &str) -> Option<MessageType>
+	{
+		MessageType::ALL.into_iter().find(|message_type| message_type.name().eq_ignore_ascii_case(name))
+	}
+	pub fn get_id(&self) -> i8
+	{
+		match self
+		{
+			MessageType::Chat => 0,
+			MessageType::Status1 => 1,
+			MessageType::Status2 => 2,
+			MessageType::Status3 => 3,
+			MessageType::BottomRight1 => 11,
+			MessageType::BottomRight2 => 12,
+			MessageType::BottomRight3 => 13,
+			MessageType::Announcement => 100
+		}
+	}
+}
+
+#[derive(PartialEq)]
+pub enum SpamCheck
+{
+	Allowed,
+	Warned,
+	Muted
+}
+// keeps track of what a single client has been saying lately
+#[derive(Default)]
+pub struct SpamFilter
+{
+	times: VecDeque<Instant>,
+	last_message: String,
+	repeats: usize,
+	warned_at: Option<Instant>
+}
+impl SpamFilter
+{
+	pub fn check(&mut self, message: &str, config: &Config) -> SpamCheck
+	{
+		if !config.spam_filter
+		{
+			return SpamCheck::Allowed;
+		}
+		self.check_at(message, Instant::now(), Duration::from_secs(config.spam_window), config.spam_max_messages, config.spam_max_repeats)
+	}
+	fn check_at(&mut self, message: &str, now: Instant, window: Duration, max_messages: usize, max_repeats: usize) -> SpamCheck
+	{
+		while self.times.front().is_some_and(|time| now.duration_since(*time) > window)
+		{
+			self.times.pop_front();
+		}
+		if self.times.is_empty() || !self.last_message.eq_ignore_ascii_case(message)
+		{
+			self.repeats = 0;
+		}
+		else
+		{
+			self.repeats += 1;
+		}
+		self.times.push_back(now);
+		self.last_message = message.to_string();
+		if self.warned_at.is_some_and(|time| now.duration_since(time) > window)
+		{
+			self.warned_at = None;
+		}
+
+		if self.times.len() <= max_messages && self.repeats < max_repeats
+		{
+			return SpamCheck::Allowed;
+		}
+		// the first time only gets a warning, keeping it up gets you muted
+		if self.warned_at.is_some()
+		{
+			*self = SpamFilter::default();
+			SpamCheck::Muted
+		}
+		else
+		{
+			self.warned_at = Some(now);
+			SpamCheck::Warned
+		}
+	}
+}
 
 pub fn wrap_and_clean(message: &str, default_mode: char) -> Vec<String>
 {
@@ -108,4 +353,121 @@ pub fn wrap_and_clean(message: &str, default_mode: char) -> Vec<String>
 		output.push(string);
 	}
 	output
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn percent_codes_become_colour_codes()
+	{
+		assert_eq!(percent_codes("%chello %Fthere"), "&chello &Fthere");
+		assert_eq!(percent_codes("100% sure, 50%"), "100% sure, 50%");
+		assert_eq!(percent_codes("%%a"), "%&a");
+	}
+
+	#[test]
+	fn strips_colours()
+	{
+		assert_eq!(strip_colors("&cred &Aand plain"), "red and plain");
+		assert_eq!(strip_colors("fish & chips &"), "fish & chips &");
+		assert_eq!(strip_colors("&&cx"), "&x");
+	}
+
+	#[test]
+	fn replaces_emotes()
+	{
+		assert_eq!(emotes("(smile) (HEART)"), "☺ ♥");
+		assert_eq!(emotes("(notes)(note)"), "♫♪");
+		assert_eq!(emotes("((>>)"), "(►");
+		assert_eq!(emotes("(unknown) (smile"), "(unknown) (smile");
+		assert_eq!(emotes("()(*)"), "()•");
+	}
+
+	#[test]
+	fn replaces_tokens()
+	{
+		let values = [("name", "Alice".to_string()), ("world", "main".to_string())];
+		assert_eq!(tokens("hi $name, welcome to $world!", &values), "hi Alice, welcome to main!");
+		assert_eq!(tokens("$names $unknown $ $", &values), "$names $unknown $ $");
+		assert_eq!(tokens("$name$world", &values), "Alicemain");
+		// values are not scanned for tokens again
+		assert_eq!(tokens("$name", &[("name", "$world".to_string()), ("world", "x".to_string())]), "$world");
+	}
+
+	#[test]
+	fn formats_in_order()
+	{
+		let values = [("name", "%cAlice".to_string())];
+		assert_eq!(format("$name (smile)", &[&|text| tokens(text, &values), &percent_codes, &emotes]), "&cAlice ☺");
+	}
+
+	const WINDOW: Duration = Duration::from_secs(4);
+	fn check(filter: &mut SpamFilter, message: &str, now: Instant) -> SpamCheck
+	{
+		filter.check_at(message, now, WINDOW, 5, 3)
+	}
+
+	#[test]
+	fn spam_filter_counts_messages_in_the_window()
+	{
+		let mut filter = SpamFilter::default();
+		let start = Instant::now();
+		for i in 0..5
+		{
+			assert!(check(&mut filter, &format!("message {}", i), start) == SpamCheck::Allowed);
+		}
+		assert!(check(&mut filter, "one too many", start) == SpamCheck::Warned);
+		// once the window passed, everything before it is forgotten
+		let later = start + WINDOW + Duration::from_secs(1);
+		assert!(check(&mut filter, "calm again", later) == SpamCheck::Allowed);
+	}
+
+	#[test]
+	fn spam_filter_counts_repeats()
+	{
+		let mut filter = SpamFilter::default();
+		let start = Instant::now();
+		for i in 0..3
+		{
+			assert!(check(&mut filter, "buy gold", start + Duration::from_secs(i)) == SpamCheck::Allowed);
+		}
+		assert!(check(&mut filter, "BUY GOLD", start + Duration::from_secs(3)) == SpamCheck::Warned);
+		// a different message starts counting again
+		assert!(check(&mut filter, "sorry", start + Duration::from_secs(3)) == SpamCheck::Allowed);
+	}
+
+	#[test]
+	fn spam_filter_mutes_after_a_warning()
+	{
+		let mut filter = SpamFilter::default();
+		let start = Instant::now();
+		for i in 0..5
+		{
+			check(&mut filter, &i.to_string(), start);
+		}
+		assert!(check(&mut filter, "b", start) == SpamCheck::Warned);
+		assert!(check(&mut filter, "c", start) == SpamCheck::Muted);
+		// muting starts over
+		assert!(check(&mut filter, "d", start) == SpamCheck::Allowed);
+	}
+
+	#[test]
+	fn spam_filter_forgets_old_warnings()
+	{
+		let mut filter = SpamFilter::default();
+		let start = Instant::now();
+		for i in 0..5
+		{
+			check(&mut filter, &i.to_string(), start);
+		}
+		assert!(check(&mut filter, "y", start) == SpamCheck::Warned);
+		let later = start + WINDOW + Duration::from_secs(1);
+		for i in 0..5
+		{
+			check(&mut filter, &i.to_string(), later);
+		}
+		assert!(check(&mut filter, "z", later) == SpamCheck::Warned);
+	}
+}
@@ -1,33 +1,55 @@
+use crate::chat::SpamFilter;
+use crate::cpe;
+use crate::cpe::Extensions;
+use crate::game::Event;
 use crate::io;
 use crate::io::AsyncReadClassicExt;
 use crate::io::AsyncWriteClassicExt;
+use crate::iprange;
+use crate::level::LevelSnapshot;
 use crate::level::SaveType;
 use crate::packet::Packet;
+use crate::profile::Position;
+use crate::queue;
+use crate::queue::PacketReceiver;
+use crate::queue::PacketSender;
 use crate::server::Server;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 use tokio::net::tcp::OwnedReadHalf;
 use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
-#[derive(PartialEq, Clone, Copy)]
+// ranks are ordered, from the least to the most trusted
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum ClientMode
 {
+	Guest, // could not be verified, so may not build or use restricted commands
 	Normal,
 	Operator
 }
 impl ClientMode
 {
+	// guests might not be who they claim, so nothing gets remembered about them under the name they gave,
+	// and the profile of whoever does own that name is not used for them either
+	pub fn keeps_profile(&self) -> bool
+	{
+		*self != ClientMode::Guest
+	}
 	pub fn get_id(&self) -> u8
 	{
 		match self
 		{
+			ClientMode::Guest => 0x00,
 			ClientMode::Normal => 0x00,
 			ClientMode::Operator => 0x64,
 		}
@@ -37,20 +59,48 @@ pub struct Client
 {
 	pub ip: SocketAddr,
 	pub username: String,
-	pub x: i16,
-	pub y: i16,
-	pub z: i16,
+	pub x: i32,
+	pub y: i32,
+	pub z: i32,
 	pub pitch: u8,
 	pub yaw: u8,
 	pub mode: ClientMode,
-	pub packet_sender: UnboundedSender<Packet>,
+	pub extensions: Extensions,
+	pub packet_sender: PacketSender,
+	pub last_activity: Instant,
+	pub afk: bool,
+	pub spam_filter: SpamFilter,
+	pub violations: u32, // movements and block changes that were rejected
+	pub alerted_at: Option<Instant>, // when operators were last told about a violation
+	pub teleported: Option<Instant>, // when the server last moved the player itself
+	pub block_times: VecDeque<Instant>, // block changes within the last second
+	pub sent: Position, // where everyone else was last told the player is
+	pub moved: bool, // since the last movement tick
+	pub stale: HashSet<i8>, // players whose movement was skipped for being far away
 }
 
 const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_MESSAGE_LEN: usize = 1024; // for messages sent in parts with LongerMessages
+
+// adds a part of a message to the ones before it, and returns the whole message once the last part arrived
+fn reassemble(partial: &mut String, message: &str, more: bool) -> Result<Option<String>, String>
+{
+	partial.push_str(message);
+	// a part shorter than a full string ended in a space that got trimmed when reading it
+	if more && message.chars().count() < io::STRING_LEN
+	{
+		partial.push(' ');
+	}
+	if partial.chars().count() > MAX_MESSAGE_LEN
+	{
+		return Err("Message too long.".to_string());
+	}
+	Ok(if more { None } else { Some(std::mem::take(partial)) })
+}
 
 impl Client
 {
-	pub async fn sender_client(mut recv: UnboundedReceiver<Packet>, write: Arc<Mutex<OwnedWriteHalf>>) -> String
+	pub async fn sender_client(mut recv: PacketReceiver, write: Arc<Mutex<OwnedWriteHalf>>, extended_positions: bool) -> String
 	{
 		while let Some(packet) = recv.recv().await
 		{
@@ -58,7 +108,7 @@ impl Client
 			{
 				return reason;
 			}
-			if let Ok(result) = tokio::time::timeout(TIMEOUT, write.lock().await.write_packet(packet)).await
+			if let Ok(result) = tokio::time::timeout(TIMEOUT, write.lock().await.write_packet_with(packet, extended_positions)).await
 			{
 				if result.is_err()
 				{
@@ -72,68 +122,44 @@ impl Client
 		}
 		"No more packets to send... server most likely crashed.".to_string()
 	}
-	pub async fn receiver_client(mut read: OwnedReadHalf, server: Arc<Mutex<Server>>, id: i8, username: String, ip: SocketAddr) -> String
+	// keeps the connection from looking dead while nothing else is being sent
+	pub async fn ping_client(packet_sender: PacketSender, interval: Duration)
 	{
-		while let Ok(result) = tokio::time::timeout(TIMEOUT, read.read_packet()).await
+		let mut interval = tokio::time::interval(interval);
+		loop
+		{
+			interval.tick().await;
+			if packet_sender.send(Packet::Ping).is_err()
+			{
+				break;
+			}
+		}
+	}
+	pub async fn receiver_client(mut read: OwnedReadHalf, events: mpsc::Sender<Event>, id: i8, timeout: Duration, extensions: Extensions) -> String
+	{
+		let (longer_messages, extended_positions) = (extensions.has(cpe::LONGER_MESSAGES), extensions.has(cpe::EXT_ENTITY_POSITIONS));
+		let mut partial = String::new();
+		while let Ok(result) = tokio::time::timeout(timeout, read.read_packet_with(extended_positions)).await
 		{
 			if let Ok(packet) = result
 			{
-				if !match packet
+				let event = match packet
 				{
-					Packet::PlaceBlock { x, y, z, block, mode } =>
-					{
-						let server = server.clone();
-						tokio::spawn(async move
-							{
-								server.lock().await.set_block(id,x,y,z, if mode == 0 { 0 } else { block }, true)
-							});
-						true
-					}
-					Packet::SetPosAndLook { id: _, x, y, z, yaw, pitch } =>
-					{
-						let server = server.clone();
-						tokio::spawn(async move
-						{
-							server.lock().await.move_player(id, id, x, y, z, yaw, pitch);
-						});
-						true
-					}
-					Packet::Message { id: _, message } =>
+					Packet::PlaceBlock { x, y, z, block, mode } => Event::PlaceBlock { id, x, y, z, block: if mode == 0 { 0 } else { block } },
+					Packet::SetPosAndLook { id: _, x, y, z, yaw, pitch } => Event::Move { id, x, y, z, yaw, pitch },
+					// with LongerMessages, an id of 1 means more of the message is still coming
+					Packet::Message { id: part, message } => match reassemble(&mut partial, &message, longer_messages && part == 1)
 					{
-						let server = server.clone();
-
-						// check if first char is /
-						if message.len() > 0 && &message[0..1] == "/"
-						{
-							tokio::spawn(async move
-							{
-								let mut split = message.split(' ');
-								let command = split.next().unwrap()[1..].to_lowercase();
-								server.lock().await.command(id, command, split.collect());
-							});
-						}
-						else if server.lock().await.config.user_data.muted.contains(&username, &ip.ip())
-						{
-							println!("{}:<{}> (muted) {}", id, username, message);
-							tokio::spawn(async move
-							{
-								server.lock().await.send_message(-1, id, "You have been muted, you cannot send any messages.");
-							});
-						}
-						else
-						{
-							let username = username.clone();
-							tokio::spawn(async move
-							{
-								server.lock().await.broadcast_message(id, &format!("<{}> {}", username, message));
-							});
-						}
-						true
-					}
-					_ => false
-				}
+						Ok(Some(message)) => Event::Message { id, message },
+						Ok(None) => continue,
+						Err(reason) => return reason
+					},
+					_ => return "Invalid packet received.".to_string()
+				};
+				// waiting here when the game loop is behind keeps a flooding client from piling up events
+				if events.send(event).await.is_err()
 				{
-					return "Invalid packet received.".to_string();
+					return "Server stopped".to_string();
 				}
 			}
 			else
@@ -143,59 +169,63 @@ impl Client
 		}
 		"Timed out".to_string()
 	}
-	pub async fn send_level(stream: &mut TcpStream, server: &Arc<Mutex<Server>>) -> Option<()>
+	// the level is a snapshot taken while spawning, so it can be compressed without holding up the server.
+	// any block changed since then is already waiting in the player's packet queue.
+	pub async fn send_level(stream: &mut TcpStream, mut level: LevelSnapshot) -> Option<()>
 	{
-		let size_x = server.lock().await.level.size_x;
-		let size_y = server.lock().await.level.size_y;
-		let size_z = server.lock().await.level.size_z;
-		let gzip = server.lock().await.level.get_gzip(SaveType::Network).unwrap();
-		let total_chunk = (gzip.len() + io::ARRAY_LEN - 1) / io::ARRAY_LEN;
-		for (i, chunk) in gzip.chunks(io::ARRAY_LEN).enumerate()
+		let (size_x, size_y, size_z) = (level.size_x, level.size_y, level.size_z);
+		let data = level.compressed().await.ok()?;
+		let total_chunk = data.len().div_ceil(io::ARRAY_LEN);
+		for (i, chunk) in data.chunks(io::ARRAY_LEN).enumerate()
 		{
 			if stream.write_packet(Packet::LevelData { length: chunk.len() as i16, data: chunk.to_vec(), percentage: ((i+1)*100/total_chunk) as u8 }).await.is_err() { return None; };
 		}
 		if stream.write_packet(Packet::LevelSize { x: size_x, y: size_y, z: size_z }).await.is_err() { return None; };
 		Some(())
 	}
-	pub async fn init_client(mut stream: TcpStream, ip: SocketAddr, server: &Arc<Mutex<Server>>) -> Option<(JoinHandle<String>, JoinHandle<String>, i8, Arc<Mutex<OwnedWriteHalf>>)>
+	pub async fn init_client(mut stream: TcpStream, ip: SocketAddr, server: &Arc<Mutex<Server>>, events: &mpsc::Sender<Event>) -> Option<(JoinHandle<String>, JoinHandle<String>, i8, Arc<Mutex<OwnedWriteHalf>>)>
 	{
 		let username;
-		if let Ok(Packet::Identification { protocol, name, data: key, user_mode: _ }) = stream.read_packet().await
+		let supports_cpe;
+		let verified;
+		if let Ok(Packet::Identification { protocol, name, data: key, user_mode: magic }) = stream.read_packet().await
 		{
 			if protocol != 0x07
 			{
 				return None;
 			}
-			if server.lock().await.config.user_data.banned.contains(&name, &ip.ip())
+			let ban_message = server.lock().await.ban_message(&name, &ip.ip());
+			if let Some(reason) = ban_message
 			{
 				println!("{} from {} tried to connect but was banned", name, ip);
-				let _ = stream.write_packet(Packet::Disconnect { reason: "You are banned.".to_string() }).await;
+				let _ = stream.write_packet(Packet::Disconnect { reason }).await;
 				return None;
 			}
-			if server.lock().await.config.verify_players
+			verified =
 			{
-				if key != format!("{:?}", md5::compute(format!("{}{}", server.lock().await.salt.clone(), name)))
-				{
-					println!("{} tried to connect but couldn't verify from {}", name, ip);
-					let _ = stream.write_packet(Packet::Disconnect { reason: "Could not verify. Try refreshing the server list.".to_string() }).await;
-					return None;
-				}
+				let server = server.lock().await;
+				!server.config.verify_players || server.verify_name(&name, &key) || (server.config.allow_unverified_lan && iprange::is_lan(&ip.ip()))
+			};
+			if !verified && !server.lock().await.config.unverified_as_guests
+			{
+				println!("{} tried to connect but couldn't verify from {}", name, ip);
+				let _ = stream.write_packet(Packet::Disconnect { reason: "Could not verify. Try refreshing the server list.".to_string() }).await;
+				return None;
 			}
 			username = name;
+			supports_cpe = magic == cpe::MAGIC;
 		}
 		else
 		{
 			return None;
 		};
-		let id = server.lock().await.first_free_space();
-		if id.is_none()
+		if server.lock().await.first_free_space().is_none()
 		{
 			println!("server too full. {} tried to connect from {}", username, ip);
 			// we do not really care if this packets fails to get sent.
 			let _ = stream.write_packet(Packet::Disconnect { reason: "Too many players.".to_string() }).await;
 			return None;
 		}
-		let id = id.unwrap();
 		if server.lock().await.get_index_from_username(&username).is_some()
 		{
 			println!("{} tried to connect a second time from {}!", username, ip);
@@ -203,52 +233,153 @@ impl Client
 			return None;
 		}
 		let server_name = server.lock().await.config.name.clone();
-		let server_motd = server.lock().await.config.motd.clone();
-		let user_mode = if server.lock().await.config.user_data.ops.contains(&username, &ip.ip()) { ClientMode::Operator } else { ClientMode::Normal };
-		println!("{}:{} is connecting from {}...", id, username, ip);
+		let server_motd = { let server = server.lock().await; server.format_text(&server.config.motd, &username) };
+		// anyone could be using the name of a guest, so it does not get them any further than the name itself
+		let user_mode = if !verified { ClientMode::Guest } else if server.lock().await.config.user_data.ops.contains(&username, &ip.ip()) { ClientMode::Operator } else { ClientMode::Normal };
+		if user_mode != ClientMode::Operator && (!server.lock().await.is_whitelisted(&username, &ip.ip()) || (user_mode == ClientMode::Guest && server.lock().await.config.whitelist_enabled))
+		{
+			println!("{} from {} tried to connect but was not whitelisted", username, ip);
+			let _ = stream.write_packet(Packet::Disconnect { reason: "You are not whitelisted on this server.".to_string() }).await;
+			return None;
+		}
+		println!("{} is connecting from {}...", username, ip);
+		let negotiate_timeout = Duration::from_secs(server.lock().await.config.read_timeout);
+		let extensions = if supports_cpe { tokio::time::timeout(negotiate_timeout, Extensions::negotiate(&mut stream)).await.ok()?? } else { Extensions::default() };
+		let fast_map = extensions.has(cpe::FAST_MAP);
+		if server.lock().await.level.needs_extended_positions() && !extensions.has(cpe::EXT_ENTITY_POSITIONS)
+		{
+			println!("{} cannot join, their client does not support large levels", username);
+			let _ = stream.write_packet(Packet::Disconnect { reason: "This level needs a client supporting ExtEntityPositions.".to_string() }).await;
+			return None;
+		}
 		if stream.write_packet(Packet::Identification { protocol: 7, name: server_name, data: server_motd, user_mode: user_mode.get_id() }).await.is_err() { return None; }
-		if stream.write_packet(Packet::LevelStart).await.is_err() { return None; }
 
 
 		// send/recv channel to receive packets asynchronously
-		let (send, recv) = mpsc::unbounded_channel();
+		let (send, recv) = { let server = server.lock().await; queue::channel(server.config.send_queue_size, server.config.send_queue_policy) };
 
 
 		// spawn the player. at this point the server starts sending things to the player
-		if server.lock().await.spawn(id, ip, username.clone(), user_mode, send).is_err() { return None; };
-
-
+		let joined =
+		{
+			let mut server = server.lock().await;
+			// checked again, someone else could have taken the slot or the name while this player was negotiating
+			match server.first_free_space()
+			{
+				None => Err("Too many players."),
+				Some(_) if server.get_index_from_username(&username).is_some() => Err("Player already logged in."),
+				Some(id) =>
+				{
+					// the player is already in the list when sending to them fails, and nothing else would take them out
+					if server.spawn(id, ip, username.clone(), user_mode, extensions.clone(), send.clone()).is_err()
+					{
+						server.disconnected(id);
+						return None;
+					}
+					if user_mode.keeps_profile()
+					{
+						server.config.user_data.identities.record(&username, ip.ip());
+						server.flag_alts(id);
+					}
+					let server = &mut *server;
+					Ok((id, server.network_cache.snapshot(&server.level, if fast_map { SaveType::FastMap } else { SaveType::Network })))
+				}
+			}
+		};
+		let (id, level) = match joined
+		{
+			Ok(joined) => joined,
+			Err(reason) =>
+			{
+				println!("{} could not join: {}", username, reason);
+				let _ = stream.write_packet(Packet::Disconnect { reason: reason.to_string() }).await;
+				return None;
+			}
+		};
+		let ping_interval = Duration::from_secs(server.lock().await.config.ping_interval.max(1));
+		tokio::spawn(Client::ping_client(send, ping_interval));
+		let level_start = if fast_map
+		{
+			Packet::LevelStartFastMap { volume: level.size_x as i32 * level.size_y as i32 * level.size_z as i32 }
+		}
+		else
+		{
+			Packet::LevelStart
+		};
 		// we send the level to the player
-		if Client::send_level(&mut stream, &server).await.is_none() { return None; };
+		if stream.write_packet(level_start).await.is_err() || Client::send_level(&mut stream, level).await.is_none()
+		{
+			let _ = events.send(Event::Disconnected { id }).await;
+			return None;
+		}
 		let (read, write) = stream.into_split();
 
 		let write = Arc::new(Mutex::new(write));
 
 		// finally we start actually sending the shit the server sends to the player
-		let sender = tokio::spawn(Client::sender_client(recv, write.clone()));
-		let receiver = tokio::spawn(Client::receiver_client(read, server.clone(), id, username, ip));
+		let sender = tokio::spawn(Client::sender_client(recv, write.clone(), extensions.has(cpe::EXT_ENTITY_POSITIONS)));
+		let read_timeout = Duration::from_secs(server.lock().await.config.read_timeout);
+		let receiver = tokio::spawn(Client::receiver_client(read, events.clone(), id, read_timeout, extensions));
 
 		Some((sender, receiver, id, write))
 	}
-	pub async fn handle_client(stream: TcpStream, ip: SocketAddr, server: Arc<Mutex<Server>>)
+	pub async fn handle_client(stream: TcpStream, ip: SocketAddr, server: Arc<Mutex<Server>>, events: mpsc::Sender<Event>)
 	{
-		let op = Client::init_client(stream, ip, &server).await;
+		let op = Client::init_client(stream, ip, &server, &events).await;
 		if op.is_none() { return; }
-		let (sender, receiver, id, write) = op.unwrap();
+		let (mut sender, mut receiver, id, write) = op.unwrap();
 
-		let reason = match tokio::select!
+		let (result, receiver_done) = tokio::select!
 		{
-			r = sender => { r }
-			r = receiver => { r }
-		}
+			r = &mut sender => { (r, false) }
+			r = &mut receiver => { (r, true) }
+		};
+		let reason = match result
 		{
 			Ok(reason) => reason,
 			Err(err) => format!("Panic: {}", err)
 		};
+		// nothing more may come from this connection once the player is gone, their id could already be someone else's
+		sender.abort();
+		if !receiver_done
+		{
+			receiver.abort();
+			let _ = receiver.await;
+		}
 
 		println!("player disconnected: \"{}\"", reason);
-		let _ = write.lock().await.write_packet(Packet::Disconnect { reason }).await;
+		let _ = tokio::time::timeout(TIMEOUT, async { write.lock().await.write_packet(Packet::Disconnect { reason }).await }).await;
 
-		server.lock().await.disconnected(id);
+		let _ = events.send(Event::Disconnected { id }).await;
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn reassembles_longer_messages()
+	{
+		let mut partial = String::new();
+		let full = "a".repeat(io::STRING_LEN);
+		assert_eq!(reassemble(&mut partial, &full, true), Ok(None));
+		// this part was cut right before a space
+		assert_eq!(reassemble(&mut partial, "hello", true), Ok(None));
+		assert_eq!(reassemble(&mut partial, "world", false), Ok(Some(format!("{}hello world", full))));
+		assert!(partial.is_empty());
+		assert_eq!(reassemble(&mut partial, "on its own", false), Ok(Some("on its own".to_string())));
+	}
+
+	#[test]
+	fn rejects_endless_messages()
+	{
+		let mut partial = String::new();
+		let full = "a".repeat(io::STRING_LEN);
+		for _ in 0..MAX_MESSAGE_LEN / io::STRING_LEN
+		{
+			assert_eq!(reassemble(&mut partial, &full, true), Ok(None));
+		}
+		assert!(reassemble(&mut partial, "b", false).is_err());
+	}
+}
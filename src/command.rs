@@ -1,7 +1,24 @@
+use chrono::Utc;
 use crate::block::BLOCKS;
+use crate::chat;
+use crate::chat::MessageType;
 use crate::client::ClientMode;
+use crate::env;
+use crate::env::EnvColor;
+use crate::env::MapProperty;
+use crate::env::Weather;
+use crate::game::Cuboid;
+use crate::game::Save;
+use crate::hacks::Hacks;
+use crate::iprange::IpRange;
+use crate::profile::Stat;
 use crate::server::Server;
+use crate::userdata::format_duration;
+use crate::userdata::format_time;
+use crate::userdata::parse_duration;
+use crate::userdata::EntryInfo;
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 #[derive(Clone)]
 pub struct Command
@@ -14,6 +31,67 @@ pub struct Command
 	pub unrestricted_only: bool,
 	pub run: fn(server: &mut Server, id: i8, args: Vec<&str>, mode: ClientMode) -> Result<(), String>
 }
+fn ban(server: &mut Server, id: i8, username: String, reason: String, duration: Option<i64>) -> Result<(), String>
+{
+	if username.is_empty()
+	{
+		return Err("No username was provided.".to_string());
+	}
+	let info = EntryInfo::new(reason, server.get_username(id), duration);
+	let msg = &format!("{} has been banned{}.", username, info.describe());
+	if let Some(bid) = server.get_index_from_username(&username)
+	{
+		if id == bid
+		{
+			return Err("You wouldn't want to ban yourself.".to_string());
+		}
+		server.kick(bid, format!("You have been banned{}", info.describe()));
+		server.broadcast_system_message(-1, msg);
+	}
+	else
+	{
+		server.send_message(-1, id, msg);
+	}
+	println!("{} banned {}{}", info.by, username, info.describe());
+	server.config.user_data.banned.add_username_with_info(username, info);
+	Ok(())
+}
+// works out whose nickname, colour or title is being changed, and to what.
+// players can change their own when their rank allows it, operators can change anyone's.
+fn appearance_target(server: &Server, id: i8, mode: ClientMode, args: &[&str], rank: ClientMode, what: &str) -> Result<(String, Option<String>), String>
+{
+	if mode < rank
+	{
+		return Err(format!("You are not allowed to change your {}.", what));
+	}
+	let (username, value) = match args
+	{
+		[] => (server.get_username(id), None),
+		[value] => (server.get_username(id), Some(value.to_string())),
+		[username, value] if mode == ClientMode::Operator => (username.to_string(), Some(value.to_string())),
+		[_, _] => return Err(format!("Only operators can change the {} of other players.", what)),
+		_ => return Err("Too many arguments.".to_string())
+	};
+	if server.profiles.get(&username).is_none()
+	{
+		return Err(format!("{} has never joined.", username));
+	}
+	// - takes it away, which is how operators clear it for someone else
+	Ok((username, value.filter(|value| value != "-")))
+}
+fn appearance_changed(server: &mut Server, id: i8, username: &str, what: &str)
+{
+	println!("{} changed the {} of {}", server.get_username(id), what, username);
+	if let Some(cid) = server.get_index_from_username(username)
+	{
+		server.update_appearance(cid);
+		if cid != id
+		{
+			server.send_message(-1, cid, &format!("Your {} has been changed.", what));
+		}
+	}
+	server.send_message(-1, id, &format!("The {} of {} has been changed.", what, username));
+}
 pub struct CommandList
 {
 	commands: HashMap<String, Command>
@@ -66,7 +144,7 @@ impl CommandList
 			unrestricted_only: false,
 			run: |server, id, _, _|
 			{
-				let rules = server.config.rules.clone();
+				let rules = server.format_text(&server.config.rules, &server.get_username(id));
 				server.send_message(-1, id, &rules);
 				Ok(())
 			}
@@ -99,46 +177,45 @@ impl CommandList
 		{
 			name: "ban",
 			desc: "Bans a user from the server.",
-			usage: "<username>",
+			usage: "<username> [reason]",
 			ops_only: true,
 			unmuted_only: false,
 			unrestricted_only: false,
 			run: |server, id, args, _|
 			{
-				let username = args.join(" ");
-				if username.is_empty()
-				{
-					return Err("No username was provided.".to_string());
-				}
-				let msg = &format!("{} has been banned.", username);
-				if let Some(bid) = server.get_index_from_username(&username)
-				{
-					if id == bid
-					{
-						return Err("You wouldn't want to ban yourself.".to_string());
-					}
-					server.kick(bid, "You have been banned.".to_string());
-					server.broadcast_system_message(-1, msg);
-				}
-				else
+				let username = args.first().unwrap_or(&"").to_string();
+				ban(server, id, username, args.get(1..).unwrap_or_default().join(" "), None)
+			}
+		});
+		commands.register(Command
+		{
+			name: "tempban",
+			desc: "Bans a user from the server for some time, e.g. 30m, 12h or 7d.",
+			usage: "<username> <duration> <reason>",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let username = args.first().unwrap_or(&"").to_string();
+				if let Some(duration) = args.get(1).and_then(|d| parse_duration(d))
 				{
-					server.send_message(-1, id, msg);
+					return ban(server, id, username, args.get(2..).unwrap_or_default().join(" "), Some(duration));
 				}
-				server.config.user_data.banned.add_username(username);
-				Ok(())
+				Err("Invalid duration, it should look like 30m, 12h or 7d.".to_string())
 			}
 		});
 		commands.register(Command
 		{
 			name: "banip",
 			desc: "IP-bans a user from the server.",
-			usage: "<username>",
+			usage: "<username> [reason]",
 			ops_only: true,
 			unmuted_only: false,
 			unrestricted_only: false,
 			run: |server, id, args, _|
 			{
-				let username = args.join(" ");
+				let username = args.first().unwrap_or(&"").to_string();
 				if username.is_empty()
 				{
 					return Err("No username was provided.".to_string());
@@ -151,8 +228,11 @@ impl CommandList
 					}
 					if let Some(client) = server.clients.get(&bid)
 					{
-						server.config.user_data.banned.add_ip(client.ip.ip());
-						server.kick(bid, "You have been banned.".to_string());
+						let ip = client.ip.ip();
+						let info = EntryInfo::new(args[1..].join(" "), server.get_username(id), None);
+						let reason = format!("You have been banned{}", info.describe());
+						server.config.user_data.banned.add_ip_with_info(ip, info);
+						server.kick(bid, reason);
 						server.broadcast_system_message(-1, &format!("{} has been banned.", username));
 						return Ok(())
 					}
@@ -161,10 +241,48 @@ impl CommandList
 			}
 		});
 		commands.register(Command
+		{
+			name: "banrange",
+			desc: "Bans a whole ip range from the server, e.g. 192.168.0.0/16.",
+			usage: "<range> [reason]",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let range = args.first().ok_or("No range was provided.")?.parse::<IpRange>()?;
+				if let Some(client) = server.clients.get(&id)
+				{
+					if range.contains(&client.ip.ip())
+					{
+						return Err("You wouldn't want to ban yourself.".to_string());
+					}
+				}
+				let info = EntryInfo::new(args[1..].join(" "), server.get_username(id), None);
+				let reason = format!("You have been banned{}", info.describe());
+				println!("{} banned {}{}", info.by, range, info.describe());
+				server.config.user_data.banned.add_range_with_info(range, info);
+				for bid in 0..server.config.max_clients
+				{
+					if let Some(client) = server.clients.get(&bid)
+					{
+						if range.contains(&client.ip.ip())
+						{
+							let username = client.username.clone();
+							server.kick(bid, reason.clone());
+							server.broadcast_system_message(-1, &format!("{} has been banned.", username));
+						}
+					}
+				}
+				server.send_message(-1, id, &format!("{} has been banned.", range));
+				Ok(())
+			}
+		});
+		commands.register(Command
 		{
 			name: "unban",
-			desc: "Unbans a user from the server.",
-			usage: "<username>",
+			desc: "Unbans a user, an ip or an ip range from the server.",
+			usage: "<username/ip/range>",
 			ops_only: true,
 			unmuted_only: false,
 			unrestricted_only: false,
@@ -175,8 +293,20 @@ impl CommandList
 				{
 					return Err("No username was provided.".to_string());
 				}
-				if server.config.user_data.banned.remove_username(&username)
-				{	
+				let removed = if username.contains('/')
+				{
+					server.config.user_data.banned.remove_range(&username.parse::<IpRange>()?)
+				}
+				else if let Ok(ip) = username.parse::<IpAddr>()
+				{
+					server.config.user_data.banned.remove_ip(&ip)
+				}
+				else
+				{
+					server.config.user_data.banned.remove_username(&username)
+				};
+				if removed
+				{
 					server.send_message(-1, id, &format!("{} has been unbanned.", username));
 				}
 				else
@@ -188,25 +318,187 @@ impl CommandList
 		});
 		commands.register(Command
 		{
-			name: "mute",
-			desc: "Mutes a user from the server.",
+			name: "whitelist",
+			desc: "Manages who is allowed to join while the whitelist is on.",
+			usage: "add <username/ip/range> || remove <username/ip/range> || list || on || off",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let target = args.get(1).unwrap_or(&"").to_string();
+				match args.first().map(|a| a.to_lowercase()).as_deref()
+				{
+					Some("add") =>
+					{
+						if target.is_empty()
+						{
+							return Err("No username was provided.".to_string());
+						}
+						if target.contains('/')
+						{
+							server.config.user_data.whitelist.add_range(target.parse::<IpRange>()?);
+						}
+						else if let Ok(ip) = target.parse::<IpAddr>()
+						{
+							server.config.user_data.whitelist.add_ip(ip);
+						}
+						else
+						{
+							server.config.user_data.whitelist.add_username(target.clone());
+						}
+						server.send_message(-1, id, &format!("{} has been added to the whitelist.", target));
+					}
+					Some("remove") =>
+					{
+						if target.is_empty()
+						{
+							return Err("No username was provided.".to_string());
+						}
+						let removed = if target.contains('/')
+						{
+							server.config.user_data.whitelist.remove_range(&target.parse::<IpRange>()?)
+						}
+						else if let Ok(ip) = target.parse::<IpAddr>()
+						{
+							server.config.user_data.whitelist.remove_ip(&ip)
+						}
+						else
+						{
+							server.config.user_data.whitelist.remove_username(&target)
+						};
+						if removed
+						{
+							server.send_message(-1, id, &format!("{} has been removed from the whitelist.", target));
+						}
+						else
+						{
+							server.send_message(-1, id, &format!("{} is not in the whitelist.", target));
+						}
+					}
+					Some("list") =>
+					{
+						let whitelist = &server.config.user_data.whitelist;
+						let entries = whitelist.usernames().iter().cloned().chain(whitelist.ips().iter().map(|ip| ip.to_string())).chain(whitelist.ranges().iter().map(|range| range.to_string())).collect::<Vec<_>>();
+						let state = if server.config.whitelist_enabled { "on" } else { "off" };
+						if entries.is_empty()
+						{
+							server.send_message(-1, id, &format!("The whitelist is {} and empty.", state));
+						}
+						else
+						{
+							server.send_message(-1, id, &format!("The whitelist is {}: {}", state, entries.join(", ")));
+						}
+					}
+					Some(state @ ("on" | "off")) =>
+					{
+						server.config.whitelist_enabled = state == "on";
+						server.config.save()?;
+						println!("{} turned the whitelist {}", server.get_username(id), state);
+						server.send_message(-1, id, &format!("The whitelist is now {}.", state));
+					}
+					_ => return Err("Invalid arguments. See /help whitelist.".to_string())
+				}
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "alts",
+			desc: "Lists the accounts that logged in from the same ip as a player.",
+			usage: "<username>",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let username = args.first().ok_or("No username was provided.")?.to_string();
+				let user_data = &server.config.user_data;
+				if user_data.identities.get(&username).is_none()
+				{
+					return Err(format!("{} has never joined.", username));
+				}
+				let alts = user_data.identities.alts(&username).iter().map(|alt| if user_data.banned.contains_username(alt) { format!("&c{}&e", alt) } else { alt.clone() }).collect::<Vec<_>>();
+				if alts.is_empty()
+				{
+					server.send_message(-1, id, &format!("{} has no known alts.", username));
+				}
+				else
+				{
+					server.send_message(-1, id, &format!("Alts of {} (&cbanned&e): {}", username, alts.join(", ")));
+				}
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "whois",
+			desc: "Shows what is known about a player.",
 			usage: "<username>",
 			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let username = args.first().ok_or("No username was provided.")?.to_string();
+				let user_data = &server.config.user_data;
+				let identity = user_data.identities.get(&username).ok_or(format!("{} has never joined.", username))?;
+				let mut str = format!("- {} -", username);
+				if let Some(client) = server.get_client_from_username(&username)
+				{
+					str.push_str(&format!("\n  Online from {}{}", client.ip.ip(), match client.mode { ClientMode::Operator => " as an operator", ClientMode::Guest => " as an unverified guest", ClientMode::Normal => "" }));
+					if let Some(app_name) = &client.extensions.app_name
+					{
+						str.push_str(&format!("\n  Client: {}", app_name));
+					}
+					if client.violations > 0
+					{
+						str.push_str(&format!("\n  Anti-cheat violations: {}", client.violations));
+					}
+				}
+				str.push_str(&format!("\n  First seen: {}\n  Last seen: {}", format_time(identity.first_seen), format_time(identity.last_seen)));
+				str.push_str(&format!("\n  IPs: {}", identity.ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ")));
+				for (name, list) in [("Banned", &user_data.banned), ("Muted", &user_data.muted), ("Restricted", &user_data.restricted)]
+				{
+					if let Some(ip) = identity.ips.iter().find(|ip| list.contains(&username, ip))
+					{
+						let info = list.get(&username, ip).map(|info| format!("{} by {}", info.describe(), info.by)).unwrap_or_default();
+						str.push_str(&format!("\n  {}{}", name, info));
+					}
+				}
+				let alts = user_data.identities.alts(&username);
+				if !alts.is_empty()
+				{
+					str.push_str(&format!("\n  Alts: {}", alts.join(", ")));
+				}
+				server.send_message(-1, id, &str);
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "mute",
+			desc: "Mutes a user from the server, optionally for some time, e.g. 10m.",
+			usage: "<username> [duration] [reason]",
+			ops_only: true,
 			unmuted_only: true,
 			unrestricted_only: false,
 			run: |server, id, args, _|
 			{
-				let username = args.join(" ");
+				let username = args.first().unwrap_or(&"").to_string();
 				if username.is_empty()
 				{
 					return Err("No username was provided.".to_string());
 				}
+				let duration = args.get(1).and_then(|d| parse_duration(d));
+				let reason = args.get(if duration.is_some() { 2.. } else { 1.. }).unwrap_or_default().join(" ");
+				let info = EntryInfo::new(reason, server.get_username(id), duration);
 				if let Some(id) = server.get_index_from_username(&username)
 				{
-					server.send_message(-1, id, "You have been muted.");
+					server.send_message(-1, id, &format!("You have been muted{}.", info.describe()));
 				}
-				server.send_message(-1, id, &format!("{} has been muted.", username));
-				server.config.user_data.muted.add_username(username);
+				server.send_message(-1, id, &format!("{} has been muted{}.", username, info.describe()));
+				server.config.user_data.muted.add_username_with_info(username, info);
 				Ok(())
 			}
 		});
@@ -308,13 +600,10 @@ impl CommandList
 				{
 					if let (Ok(x),Ok(y),Ok(z)) = (x.parse::<i16>(),y.parse::<i16>(),z.parse::<i16>())
 					{
-						if x <= i16::MAX / 32 && x >= i16::MIN / 32 && y <= i16::MAX / 32 - 1 && y >= i16::MIN / 32 && z < i16::MAX / 32 && z >= i16::MIN / 32
-						{
-							let client = server.clients.get(&id).unwrap();
-							server.move_player(id, -1, x*32+16, y*32+61, z*32+16, client.yaw, client.pitch);
-							server.send_message(-1, id, &format!("Teleported to coordinate {} {} {}.", x, y, z));
-							return Ok(());
-						}
+						let client = server.clients.get(&id).unwrap();
+						server.move_player(id, -1, x as i32*32+16, y as i32*32+61, z as i32*32+16, client.yaw, client.pitch);
+						server.send_message(-1, id, &format!("Teleported to coordinate {} {} {}.", x, y, z));
+						return Ok(());
 					}
 				}
 				if let Some(target) = args.get(0)
@@ -348,14 +637,11 @@ impl CommandList
 						{
 							if let (Ok(x),Ok(y),Ok(z)) = (x.parse::<i16>(),y.parse::<i16>(),z.parse::<i16>())
 							{
-								if x <= i16::MAX / 32 && x >= i16::MIN / 32 && y <= i16::MAX / 32 - 1 && y >= i16::MIN / 32 && z < i16::MAX / 32 && z >= i16::MIN / 32
-								{
-									let client = server.clients.get(&id).unwrap();
-									server.move_player(id, -1, x*32+16, y*32+61, z*32+16, client.yaw, client.pitch);
-									server.send_message(-1, fid, &format!("Teleported {} to coordinate {} {} {}.", username, x, y, z));
-									server.send_message(-1, id, &format!("Teleported by {} to coordinate {} {} {}.", fusername, x, y, z));
-									return Ok(());
-								}
+								let client = server.clients.get(&id).unwrap();
+								server.move_player(id, -1, x as i32*32+16, y as i32*32+61, z as i32*32+16, client.yaw, client.pitch);
+								server.send_message(-1, fid, &format!("Teleported {} to coordinate {} {} {}.", username, x, y, z));
+								server.send_message(-1, id, &format!("Teleported by {} to coordinate {} {} {}.", fusername, x, y, z));
+								return Ok(());
 							}
 						}
 						if let Some(target) = args.get(1)
@@ -385,11 +671,205 @@ impl CommandList
 			run: |server, fid, _, _|
 			{
 				server.broadcast_message(-1, "Saving world...");
-				if let Err(e) = server.level.save()
+				server.broadcast_message_type(MessageType::Status2, "&eSaving world...");
+				server.config.user_data.identities.save_changes();
+				server.jobs.push_back(Box::new(Save::new(fid)));
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "info",
+			desc: "Shows the statistics of a player.",
+			usage: "[username]",
+			ops_only: false,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let username = args.first().map(|a| a.to_string()).unwrap_or_else(|| server.get_username(id));
+				let profile = server.profiles.get(&username).ok_or(format!("{} has never joined.", username))?;
+				let online = server.get_index_from_username(&username).is_some();
+				let session = if online { (Utc::now().timestamp() - profile.last_login).max(0) as u64 } else { 0 };
+				let mut str = format!("- {} -", username);
+				str.push_str(&format!("\n  {}", if online { "&aOnline&e" } else { "Offline" }));
+				str.push_str(&format!("\n  First login: {}\n  Last login: {}", format_time(profile.first_login), format_time(profile.last_login)));
+				str.push_str(&format!("\n  Time online: {} over {} logins", format_duration((profile.time_online + session) as i64), profile.logins));
+				str.push_str(&format!("\n  Blocks placed: {}, destroyed: {}", profile.blocks_placed, profile.blocks_destroyed));
+				str.push_str(&format!("\n  Messages sent: {}", profile.messages_sent));
+				server.send_message(-1, id, &str);
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "top",
+			desc: "Shows the players with the highest statistic.",
+			usage: "<time/logins/placed/destroyed/messages>",
+			ops_only: false,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let stat = args.first().and_then(|name| Stat::from_name(name)).ok_or(format!("Unknown statistic, try one of: {}", Stat::ALL.map(|stat| stat.name()).join(", ")))?;
+				let now = Utc::now().timestamp();
+				let top = server.profiles.top(stat, 10, |username|
+				{
+					match (stat, server.profiles.get(username))
+					{
+						(Stat::TimeOnline, Some(profile)) if server.get_client_from_username(username).is_some_and(|client| client.mode.keeps_profile()) => (now - profile.last_login).max(0) as u64,
+						_ => 0
+					}
+				});
+				let mut str = format!("Top players by {}:", stat.name());
+				for (i, (username, value)) in top.iter().enumerate()
+				{
+					let value = if stat == Stat::TimeOnline { format_duration(*value as i64) } else { value.to_string() };
+					str.push_str(&format!("\n  {}. {} - {}", i + 1, username, value));
+				}
+				server.send_message(-1, id, &str);
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "afk",
+			desc: "Lets everyone know you are away. Moving or talking brings you back.",
+			usage: "[reason]",
+			ops_only: false,
+			unmuted_only: true,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				if id < 0
+				{
+					return Err("The console cannot be AFK.".to_string());
+				}
+				server.set_afk(id, &args.join(" "));
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "nick",
+			desc: "Changes your nickname, or takes it away when left out.",
+			usage: "[player] [nickname/-]",
+			ops_only: false,
+			unmuted_only: true,
+			unrestricted_only: false,
+			run: |server, id, args, mode|
+			{
+				let (username, nick) = appearance_target(server, id, mode, &args, server.config.nick_rank, "nickname")?;
+				if let Some(nick) = &nick
+				{
+					if nick.is_empty() || nick.len() > 16 || !nick.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+					{
+						return Err("Nicknames are up to 16 letters, numbers and underscores.".to_string());
+					}
+					// nobody gets to pass for someone else
+					let taken = !nick.eq_ignore_ascii_case(&username) && (server.profiles.has_name(nick) || server.clients.values().any(|client| nick.eq_ignore_ascii_case(&server.appearance(&client.username).0)));
+					if taken
+					{
+						return Err(format!("{} is already someone's name.", nick));
+					}
+				}
+				server.profiles.get_mut(&username).nick = nick.unwrap_or_default();
+				appearance_changed(server, id, &username, "nickname");
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "color",
+			desc: "Changes the colour of your name, or takes it away when left out.",
+			usage: "[player] [colour/-]",
+			ops_only: false,
+			unmuted_only: true,
+			unrestricted_only: false,
+			run: |server, id, args, mode|
+			{
+				let (username, color) = appearance_target(server, id, mode, &args, server.config.color_rank, "colour")?;
+				let color = match color
+				{
+					Some(color) => Some(chat::parse_color(&color).ok_or(format!("Unknown colour, try one of: {}", chat::color_names()))?),
+					None => None
+				};
+				server.profiles.get_mut(&username).color = color;
+				appearance_changed(server, id, &username, "colour");
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "title",
+			desc: "Changes the title shown in front of your messages, or takes it away when left out.",
+			usage: "[player] [title/-]",
+			ops_only: false,
+			unmuted_only: true,
+			unrestricted_only: false,
+			run: |server, id, args, mode|
+			{
+				let (username, title) = appearance_target(server, id, mode, &args, server.config.title_rank, "title")?;
+				if let Some(title) = &title
+				{
+					if title.len() > 16 || !title.chars().all(|c| c.is_ascii_graphic() && c != '&')
+					{
+						return Err("Titles are up to 16 characters, without colour codes.".to_string());
+					}
+				}
+				server.profiles.get_mut(&username).title = title.unwrap_or_default();
+				appearance_changed(server, id, &username, "title");
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "netstats",
+			desc: "Shows how well the server keeps up with sending packets to each player.",
+			usage: "[username]",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let mut str = "Send queues (queued/peak, sent, coalesced, dropped):".to_string();
+				for cid in 0..server.config.max_clients
+				{
+					if let Some(client) = server.clients.get(&cid)
+					{
+						if args.first().is_some_and(|username| *username != client.username)
+						{
+							continue;
+						}
+						let stats = client.packet_sender.stats();
+						str.push_str(&format!("\n  {}: {}/{}, {}, {}, {}", client.username, stats.queued, stats.peak, stats.sent, stats.coalesced, stats.dropped));
+					}
+				}
+				server.send_message(-1, id, &str);
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "announce",
+			desc: "Shows a message to everyone, in the middle of the screen unless told otherwise.",
+			usage: "[chat/status1/status2/status3/bottom1/bottom2/bottom3/center] <message>",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let (message_type, message) = match args.first().and_then(|name| MessageType::from_name(name))
 				{
-					server.send_message(-1, fid, &e);
+					Some(message_type) => (message_type, args[1..].join(" ")),
+					None => (MessageType::Announcement, args.join(" "))
+				};
+				if message.is_empty() && message_type == MessageType::Chat
+				{
+					return Err("Please type in a message to announce.".to_string());
 				}
-				server.broadcast_message(-1, "Done.");
+				let message = server.format_text(&message, &server.get_username(id));
+				server.broadcast_message_type(message_type, &message);
 				Ok(())
 			}
 		});
@@ -422,13 +902,17 @@ impl CommandList
 				{
 					if let Some(id) = server.get_index_from_username(username)
 					{
-						let msg = args.map(|x| *x).collect::<Vec<&str>>().join(" ");
+						let msg = server.format_chat(fid, &args.map(|x| *x).collect::<Vec<&str>>().join(" "));
 						if msg.is_empty()
 						{
 							return Err("Please type in a message to send.".to_string());
 						}
 						server.send_message(fid, fid, &format!("&7to <{}> {}", username, msg));
 						server.send_message(fid, id, &format!("&7<{}> {}", sender, msg));
+						if server.clients.get(&id).is_some_and(|client| client.afk)
+						{
+							server.send_message(-1, fid, &format!("{} is AFK, they may not answer right away.", username));
+						}
 						return Ok(());
 					}
 				}
@@ -457,6 +941,146 @@ impl CommandList
 			}
 		});
 		commands.register(Command
+		{
+			name: "env",
+			desc: "Changes how the world looks. Without arguments, shows the current settings.",
+			usage: "[sky/cloud/fog/ambient/diffuse <#rrggbb>] [weather <sun/rain/snow>] [side/edge <block>] [water/clouds/distance <number>], or - to reset",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let (property, value) = match args[..]
+				{
+					[] =>
+					{
+						let env = &server.level.env;
+						let mut str = format!("- Environment of {} -", server.level.name);
+						for color in EnvColor::ALL
+						{
+							let value = env.color(color).map_or("default".to_string(), |[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b));
+							str.push_str(&format!("\n  {}: {}", color.name(), value));
+						}
+						str.push_str(&format!("\n  weather: {}", ["sun", "rain", "snow"][env.weather as usize]));
+						for property in MapProperty::ALL
+						{
+							str.push_str(&format!("\n  {}: {}", property.name(), env.property(property, server.level.size_y)));
+						}
+						str.push_str(&format!("\n  texture: {}", if env.texture_url.is_empty() { "default" } else { &env.texture_url }));
+						server.send_message(-1, id, &str);
+						return Ok(());
+					}
+					[property, value] => (property, value),
+					_ => return Err("Invalid arguments.".to_string())
+				};
+				let reset = value == "-";
+				if let Some(color) = EnvColor::from_name(property)
+				{
+					let rgb = if reset { None } else { Some(env::parse_color(value).ok_or("Colours are written like #ff8000.")?) };
+					server.level.env.set_color(color, rgb);
+				}
+				else if property.eq_ignore_ascii_case("weather")
+				{
+					server.level.env.weather = if reset { Weather::default() } else { Weather::from_name(value).ok_or("The weather can be sun, rain or snow.")? };
+				}
+				else if let Some(property) = MapProperty::from_name(property)
+				{
+					let value = if reset { None } else { Some(value.parse::<i32>().map_err(|_| format!("{} is not a number.", value))?) };
+					server.level.env.set_property(property, value)?;
+				}
+				else
+				{
+					return Err(format!("Unknown setting {}.", property));
+				}
+				server.update_env();
+				server.send_message(-1, id, &format!("Changed the {} of {}.", property, server.level.name));
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "texture",
+			desc: "Changes the texture pack of the world.",
+			usage: "<url of a .zip or .png/->",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let url = args.first().ok_or("No url was provided.")?.to_string();
+				if url == "-"
+				{
+					server.level.env.texture_url = String::new();
+				}
+				else
+				{
+					if !(url.starts_with("http://") || url.starts_with("https://")) || !(url.ends_with(".zip") || url.ends_with(".png"))
+					{
+						return Err("The url has to start with http:// or https:// and end with .zip or .png.".to_string());
+					}
+					if url.len() > 64
+					{
+						return Err("The url cannot be longer than 64 characters.".to_string());
+					}
+					server.level.env.texture_url = url;
+				}
+				server.update_env();
+				server.send_message(-1, id, &format!("Changed the texture pack of {}.", server.level.name));
+				Ok(())
+			}
+		});
+		commands.register(Command
+		{
+			name: "hacks",
+			desc: "Changes which hacks are allowed in the world. Without arguments, shows the current settings.",
+			usage: "[flying/noclip/speeding/respawn/thirdperson <on/off>] [jump <height/->]",
+			ops_only: true,
+			unmuted_only: false,
+			unrestricted_only: false,
+			run: |server, id, args, _|
+			{
+				let (hack, value) = match args[..]
+				{
+					[] =>
+					{
+						let hacks = &server.level.hacks;
+						let mut str = format!("- Hacks in {} -", server.level.name);
+						for name in Hacks::NAMES
+						{
+							let allowed = hacks.get(name).unwrap_or_default();
+							str.push_str(&format!("\n  {}: {}", name, if allowed { "allowed" } else { "disallowed" }));
+						}
+						str.push_str(&format!("\n  jump: {}", hacks.jump_height.map_or("normal".to_string(), |height| height.to_string())));
+						server.send_message(-1, id, &str);
+						return Ok(());
+					}
+					[hack, value] => (hack, value),
+					_ => return Err("Invalid arguments.".to_string())
+				};
+				if hack.eq_ignore_ascii_case("jump")
+				{
+					server.level.hacks.jump_height = if value == "-" { None } else { Some(value.parse::<i16>().ok().filter(|height| *height >= 0).ok_or(format!("{} is not a valid height.", value))?) };
+				}
+				else
+				{
+					let allowed = match value.to_lowercase().as_str()
+					{
+						"on" | "yes" | "true" => true,
+						"off" | "no" | "false" => false,
+						_ => return Err("Hacks can be on or off.".to_string())
+					};
+					*server.level.hacks.get_mut(hack).ok_or(format!("Unknown hack, try one of: {}, jump", Hacks::NAMES.join(", ")))? = allowed;
+				}
+				server.level.changed = true;
+				for cid in 0..server.config.max_clients
+				{
+					server.send_hacks(cid);
+				}
+				server.send_message(-1, id, &format!("Changed {} in {}.", hack, server.level.name));
+				Ok(())
+			}
+		});
+		commands.register(Command
 		{
 			name: "cuboid",
 			desc: "Creates a cube",
@@ -480,16 +1104,7 @@ impl CommandList
 						{
 							return Err("Block out of bound.".to_string());
 						}
-						for x in i16::min(x1,x2)..=i16::max(x1,x2)
-						{
-							for y in i16::min(y1,y2)..=i16::max(y1,y2)
-							{
-								for z in i16::min(z1,z2)..=i16::max(z1,z2)
-								{
-									server.set_block(id, x, y, z, block, false);
-								}
-							}
-						}
+						server.jobs.push_back(Box::new(Cuboid::new(id, (x1, y1, z1), (x2, y2, z2), block)));
 						return Ok(());
 					}
 				}
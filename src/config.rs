@@ -1,4 +1,7 @@
+use crate::client::ClientMode;
 use crate::level::GenerationType;
+use crate::queue::QueuePolicy;
+use crate::server::PlayerListGroup;
 use crate::userdata::UserData;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
@@ -22,9 +25,38 @@ fn default_level_size_z() -> i16 { 128 }
 fn default_level_type() -> GenerationType { GenerationType::Flat }
 fn default_level_seed() -> u64 { SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs() }
 fn default_heartbeat() -> bool { false }
-fn default_heartbeat_address() -> String { "".to_string() }
+fn default_heartbeat_urls() -> Vec<String> { vec!["https://www.classicube.net/server/heartbeat".to_string()] }
 fn default_verify_players() -> bool { false }
+fn default_allow_unverified_lan() -> bool { false }
+fn default_unverified_as_guests() -> bool { false }
 fn default_public() -> bool { false }
+fn default_ping_interval() -> u64 { 5 }
+fn default_read_timeout() -> u64 { 10 }
+fn default_afk_time() -> u64 { 300 }
+fn default_afk_kick_time() -> u64 { 0 }
+fn default_send_queue_size() -> usize { 16384 }
+fn default_send_queue_policy() -> QueuePolicy { QueuePolicy::Coalesce }
+fn default_whitelist_enabled() -> bool { false }
+fn default_flag_banned_alts() -> bool { false }
+fn default_spam_filter() -> bool { true }
+fn default_spam_max_messages() -> usize { 5 }
+fn default_spam_max_repeats() -> usize { 3 }
+fn default_spam_window() -> u64 { 4 }
+fn default_spam_mute_duration() -> i64 { 60 }
+fn default_player_list_group() -> PlayerListGroup { PlayerListGroup::World }
+fn default_nick_rank() -> ClientMode { ClientMode::Normal }
+fn default_color_rank() -> ClientMode { ClientMode::Normal }
+fn default_title_rank() -> ClientMode { ClientMode::Operator }
+fn default_chat_color_rank() -> ClientMode { ClientMode::Normal }
+fn default_hacks_exempt_rank() -> ClientMode { ClientMode::Operator }
+fn default_max_move_distance() -> f32 { 5.0 }
+fn default_max_speeding_move_distance() -> f32 { 40.0 }
+fn default_max_reach() -> f32 { 6.0 }
+fn default_max_blocks_per_second() -> usize { 15 }
+fn default_position_resync_interval() -> u64 { 10 }
+fn default_movement_interval() -> u64 { 100 }
+fn default_far_movement_distance() -> f32 { 0.0 }
+fn default_far_movement_interval() -> u64 { 500 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Config
@@ -53,12 +85,72 @@ pub struct Config
 	pub level_seed: u64,
 	#[serde(default = "default_heartbeat")]
 	pub heartbeat: bool,
-	#[serde(default = "default_heartbeat_address")]
-	pub heartbeat_address: String,
+	#[serde(default = "default_heartbeat_urls")]
+	pub heartbeat_urls: Vec<String>, // every server list to announce the server to
+	#[serde(default, skip_serializing)]
+	heartbeat_address: String, // the single list older versions had, becomes heartbeat_urls when loading a config without it
 	#[serde(default = "default_verify_players")]
-	pub verify_players: bool,
+	pub verify_players: bool, // against the salt of every server list in heartbeat_urls
+	#[serde(default = "default_allow_unverified_lan")]
+	pub allow_unverified_lan: bool, // players from loopback and private addresses do not need to be verified
+	#[serde(default = "default_unverified_as_guests")]
+	pub unverified_as_guests: bool, // let players who could not be verified join as guests instead of kicking them
 	#[serde(default = "default_public")]
 	pub public: bool,
+	#[serde(default = "default_ping_interval")]
+	pub ping_interval: u64, // in seconds
+	#[serde(default = "default_read_timeout")]
+	pub read_timeout: u64, // in seconds
+	#[serde(default = "default_afk_time")]
+	pub afk_time: u64, // in seconds of not moving or talking, 0 to disable
+	#[serde(default = "default_afk_kick_time")]
+	pub afk_kick_time: u64, // in seconds of not moving or talking, 0 to disable
+	#[serde(default = "default_send_queue_size")]
+	pub send_queue_size: usize, // packets waiting to be sent to a single client
+	#[serde(default = "default_send_queue_policy")]
+	pub send_queue_policy: QueuePolicy, // what to do once a client's queue is full
+	#[serde(default = "default_movement_interval")]
+	pub movement_interval: u64, // in milliseconds between sending everyone's movement, 0 to send it right away
+	#[serde(default = "default_far_movement_distance")]
+	pub far_movement_distance: f32, // in blocks, players farther apart see each other move less often. 0 to disable
+	#[serde(default = "default_far_movement_interval")]
+	pub far_movement_interval: u64, // in milliseconds, how often far away players see each other move
+	#[serde(default = "default_position_resync_interval")]
+	pub position_resync_interval: u64, // in seconds between sending everyone's full position, 0 to disable
+	#[serde(default = "default_whitelist_enabled")]
+	pub whitelist_enabled: bool,
+	#[serde(default = "default_flag_banned_alts")]
+	pub flag_banned_alts: bool, // warn operators when someone shares an ip with a banned player
+	#[serde(default = "default_spam_filter")]
+	pub spam_filter: bool,
+	#[serde(default = "default_spam_max_messages")]
+	pub spam_max_messages: usize, // messages allowed within the spam window
+	#[serde(default = "default_spam_max_repeats")]
+	pub spam_max_repeats: usize, // times the same message can be repeated within the spam window
+	#[serde(default = "default_spam_window")]
+	pub spam_window: u64, // in seconds
+	#[serde(default = "default_spam_mute_duration")]
+	pub spam_mute_duration: i64, // in seconds
+	#[serde(default = "default_player_list_group")]
+	pub player_list_group: PlayerListGroup, // how players are grouped in the tab list of clients that support it
+	#[serde(default = "default_nick_rank")]
+	pub nick_rank: ClientMode, // lowest rank allowed to pick their own nickname
+	#[serde(default = "default_color_rank")]
+	pub color_rank: ClientMode,
+	#[serde(default = "default_title_rank")]
+	pub title_rank: ClientMode,
+	#[serde(default = "default_chat_color_rank")]
+	pub chat_color_rank: ClientMode, // lowest rank allowed to use colour codes in chat
+	#[serde(default = "default_hacks_exempt_rank")]
+	pub hacks_exempt_rank: ClientMode, // lowest rank that ignores the hacks a level disallows, and the movement and block checks
+	#[serde(default = "default_max_move_distance")]
+	pub max_move_distance: f32, // in blocks between two positions sent by a player, 0 to disable
+	#[serde(default = "default_max_speeding_move_distance")]
+	pub max_speeding_move_distance: f32, // the same, in levels that allow speeding
+	#[serde(default = "default_max_reach")]
+	pub max_reach: f32, // in blocks from a player's eyes to the middle of a block they change, 0 to disable
+	#[serde(default = "default_max_blocks_per_second")]
+	pub max_blocks_per_second: usize, // blocks a player can change in a second, 0 to disable
 
 	#[serde(skip, default = "UserData::load")]
 	pub user_data: UserData,
@@ -82,9 +174,39 @@ impl Default for Config
 			level_type: default_level_type(),
 			level_seed: default_level_seed(),
 			heartbeat: default_heartbeat(),
-			heartbeat_address: default_heartbeat_address(),
+			heartbeat_urls: default_heartbeat_urls(),
+			heartbeat_address: String::new(),
 			verify_players: default_verify_players(),
+			allow_unverified_lan: default_allow_unverified_lan(),
+			unverified_as_guests: default_unverified_as_guests(),
 			public: default_public(),
+			ping_interval: default_ping_interval(),
+			read_timeout: default_read_timeout(),
+			afk_time: default_afk_time(),
+			afk_kick_time: default_afk_kick_time(),
+			send_queue_size: default_send_queue_size(),
+			send_queue_policy: default_send_queue_policy(),
+			movement_interval: default_movement_interval(),
+			far_movement_distance: default_far_movement_distance(),
+			far_movement_interval: default_far_movement_interval(),
+			position_resync_interval: default_position_resync_interval(),
+			whitelist_enabled: default_whitelist_enabled(),
+			flag_banned_alts: default_flag_banned_alts(),
+			spam_filter: default_spam_filter(),
+			spam_max_messages: default_spam_max_messages(),
+			spam_max_repeats: default_spam_max_repeats(),
+			spam_window: default_spam_window(),
+			spam_mute_duration: default_spam_mute_duration(),
+			player_list_group: default_player_list_group(),
+			nick_rank: default_nick_rank(),
+			color_rank: default_color_rank(),
+			title_rank: default_title_rank(),
+			chat_color_rank: default_chat_color_rank(),
+			hacks_exempt_rank: default_hacks_exempt_rank(),
+			max_move_distance: default_max_move_distance(),
+			max_speeding_move_distance: default_max_speeding_move_distance(),
+			max_reach: default_max_reach(),
+			max_blocks_per_second: default_max_blocks_per_second(),
 			user_data: UserData::load()
 		}
     }
@@ -95,12 +217,20 @@ impl Config
 
 	pub fn load() -> Result<Self, String>
 	{
-		let config;
+		let mut config: Config;
 		let mut firsttime = false;
 
 		if let Ok(file) = File::open(Config::FILE)
 		{
-			config = serde_json::from_reader(file).unwrap();
+			let json: serde_json::Value = serde_json::from_reader(file).unwrap();
+			let has_urls = json.get("heartbeat_urls").is_some();
+			config = serde_json::from_value(json).unwrap();
+			// the list an older config announced to replaces the default one, rather than announcing to both
+			let old_address = std::mem::take(&mut config.heartbeat_address);
+			if !old_address.is_empty() && !has_urls
+			{
+				config.heartbeat_urls = vec![old_address];
+			}
 		}
 		else
 		{
@@ -108,7 +238,18 @@ impl Config
 			config = Config::default();
 		}
 
-		if let (Ok(json), Ok(mut file)) = (serde_json::to_string_pretty(&config), File::create(Config::FILE))
+		config.save()?;
+
+		if firsttime
+		{
+			return Err("config file was not found, so one was created. please configure it, and then you can run the program again. add your username to the operator list as well.".to_string());
+		}
+
+		Ok(config)
+	}
+	pub fn save(&self) -> Result<(), String>
+	{
+		if let (Ok(json), Ok(mut file)) = (serde_json::to_string_pretty(self), File::create(Config::FILE))
 		{
 			if file.write(json.as_bytes()).is_err()
 			{
@@ -119,12 +260,6 @@ impl Config
 		{
 			return Err("could not create config file.".to_string());
 		}
-
-		if firsttime
-		{
-			return Err("config file was not found, so one was created. please configure it, and then you can run the program again. add your username to the operator list as well.".to_string());
-		}
-
-		Ok(config)
+		Ok(())
 	}
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+// Classic Protocol Extension negotiation.
+// a client supporting it sets the unused byte of its identification to 0x42, and both sides then
+// exchange the list of extensions they know before the server identifies itself.
+
+use crate::io::AsyncReadClassicExt;
+use crate::io::AsyncWriteClassicExt;
+use crate::packet::Packet;
+use std::collections::HashMap;
+use tokio::net::TcpStream;
+
+pub const MAGIC: u8 = 0x42;
+pub const APP_NAME: &str = "chipscraft";
+
+pub const FAST_MAP: &str = "FastMap";
+pub const EXT_PLAYER_LIST: &str = "ExtPlayerList";
+pub const LONGER_MESSAGES: &str = "LongerMessages";
+pub const MESSAGE_TYPES: &str = "MessageTypes";
+pub const ENV_COLORS: &str = "EnvColors";
+pub const ENV_WEATHER_TYPE: &str = "EnvWeatherType";
+pub const ENV_MAP_ASPECT: &str = "EnvMapAspect";
+pub const HACK_CONTROL: &str = "HackControl";
+pub const EXT_ENTITY_POSITIONS: &str = "ExtEntityPositions";
+
+pub const EXTENSIONS: [(&str, i32); 9] =
+[
+	(FAST_MAP, 1),
+	(EXT_PLAYER_LIST, 2),
+	(LONGER_MESSAGES, 1),
+	(MESSAGE_TYPES, 1),
+	(ENV_COLORS, 1),
+	(ENV_WEATHER_TYPE, 1),
+	(ENV_MAP_ASPECT, 1),
+	(HACK_CONTROL, 1),
+	(EXT_ENTITY_POSITIONS, 1),
+];
+
+// the extensions both the server and a client agreed on
+#[derive(Default, Clone)]
+pub struct Extensions
+{
+	pub app_name: Option<String>, // None for clients without CPE
+	versions: HashMap<String, i32>
+}
+impl Extensions
+{
+	pub fn has(&self, name: &str) -> bool
+	{
+		self.versions.contains_key(name)
+	}
+	pub async fn negotiate(stream: &mut TcpStream) -> Option<Extensions>
+	{
+		stream.write_packet(Packet::ExtInfo { app_name: APP_NAME.to_string(), extension_count: EXTENSIONS.len() as i16 }).await.ok()?;
+		for (name, version) in EXTENSIONS
+		{
+			stream.write_packet(Packet::ExtEntry { name: name.to_string(), version }).await.ok()?;
+		}
+		let (app_name, extension_count) = match stream.read_packet().await.ok()?
+		{
+			Packet::ExtInfo { app_name, extension_count } => (app_name, extension_count),
+			_ => return None
+		};
+		let mut extensions = Extensions { app_name: Some(app_name), ..Default::default() };
+		for _ in 0..extension_count
+		{
+			if let Packet::ExtEntry { name, version } = stream.read_packet().await.ok()?
+			{
+				if EXTENSIONS.iter().any(|(n, v)| *n == name && *v == version)
+				{
+					extensions.versions.insert(name, version);
+				}
+			}
+			else
+			{
+				return None;
+			}
+		}
+		println!("client is using {} with {}/{} known extensions", extensions.app_name.as_deref().unwrap_or_default(), extensions.versions.len(), extension_count);
+		Some(extensions)
+	}
+}
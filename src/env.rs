@@ -0,0 +1,203 @@
+use crate::block;
+use crate::packet::Packet;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Weather
+{
+	#[default]
+	Sunny,
+	Raining,
+	Snowing
+}
+impl Weather
+{
+	pub fn from_name(name: &str) -> Option<Weather>
+	{
+		match name.to_lowercase().as_str()
+		{
+			"sun" | "sunny" => Some(Weather::Sunny),
+			"rain" | "raining" => Some(Weather::Raining),
+			"snow" | "snowing" => Some(Weather::Snowing),
+			_ => None
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EnvColor
+{
+	Sky,
+	Cloud,
+	Fog,
+	Ambient,
+	Diffuse
+}
+impl EnvColor
+{
+	pub const ALL: [EnvColor; 5] = [EnvColor::Sky, EnvColor::Cloud, EnvColor::Fog, EnvColor::Ambient, EnvColor::Diffuse];
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			EnvColor::Sky => "sky",
+			EnvColor::Cloud => "cloud",
+			EnvColor::Fog => "fog",
+			EnvColor::Ambient => "ambient",
+			EnvColor::Diffuse => "diffuse"
+		}
+	}
+	pub fn from_name(name: &str) -> Option<EnvColor>
+	{
+		EnvColor::ALL.into_iter().find(|color| color.name().eq_ignore_ascii_case(name))
+	}
+}
+
+// the properties of EnvMapAspect, in the order of their ids
+#[derive(Clone, Copy, PartialEq)]
+pub enum MapProperty
+{
+	SideBlock,
+	EdgeBlock,
+	EdgeHeight,
+	CloudsHeight,
+	ViewDistance
+}
+impl MapProperty
+{
+	pub const ALL: [MapProperty; 5] = [MapProperty::SideBlock, MapProperty::EdgeBlock, MapProperty::EdgeHeight, MapProperty::CloudsHeight, MapProperty::ViewDistance];
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			MapProperty::SideBlock => "side",
+			MapProperty::EdgeBlock => "edge",
+			MapProperty::EdgeHeight => "water",
+			MapProperty::CloudsHeight => "clouds",
+			MapProperty::ViewDistance => "distance"
+		}
+	}
+	pub fn from_name(name: &str) -> Option<MapProperty>
+	{
+		MapProperty::ALL.into_iter().find(|property| property.name().eq_ignore_ascii_case(name))
+	}
+}
+
+// how a level looks to clients that support the Env extensions, saved along with the level
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Environment
+{
+	#[serde(default)]
+	pub sky_color: Option<[u8; 3]>,
+	#[serde(default)]
+	pub cloud_color: Option<[u8; 3]>,
+	#[serde(default)]
+	pub fog_color: Option<[u8; 3]>,
+	#[serde(default)]
+	pub ambient_color: Option<[u8; 3]>,
+	#[serde(default)]
+	pub diffuse_color: Option<[u8; 3]>,
+	#[serde(default)]
+	pub weather: Weather,
+	#[serde(default)]
+	pub side_block: Option<u8>,
+	#[serde(default)]
+	pub edge_block: Option<u8>,
+	#[serde(default)]
+	pub edge_height: Option<i32>,
+	#[serde(default)]
+	pub clouds_height: Option<i32>,
+	#[serde(default)]
+	pub view_distance: Option<i32>, // 0 means no limit
+	#[serde(default)]
+	pub texture_url: String // empty for the default textures
+}
+impl Environment
+{
+	pub fn color(&self, color: EnvColor) -> Option<[u8; 3]>
+	{
+		match color
+		{
+			EnvColor::Sky => self.sky_color,
+			EnvColor::Cloud => self.cloud_color,
+			EnvColor::Fog => self.fog_color,
+			EnvColor::Ambient => self.ambient_color,
+			EnvColor::Diffuse => self.diffuse_color
+		}
+	}
+	pub fn set_color(&mut self, color: EnvColor, rgb: Option<[u8; 3]>)
+	{
+		match color
+		{
+			EnvColor::Sky => self.sky_color = rgb,
+			EnvColor::Cloud => self.cloud_color = rgb,
+			EnvColor::Fog => self.fog_color = rgb,
+			EnvColor::Ambient => self.ambient_color = rgb,
+			EnvColor::Diffuse => self.diffuse_color = rgb
+		}
+	}
+	// unset properties still get sent with their default value, so changing back to it works too
+	pub fn property(&self, property: MapProperty, size_y: i16) -> i32
+	{
+		match property
+		{
+			MapProperty::SideBlock => self.side_block.unwrap_or(7) as i32,
+			MapProperty::EdgeBlock => self.edge_block.unwrap_or(8) as i32,
+			MapProperty::EdgeHeight => self.edge_height.unwrap_or(size_y as i32 / 2),
+			MapProperty::CloudsHeight => self.clouds_height.unwrap_or(size_y as i32 + 2),
+			MapProperty::ViewDistance => self.view_distance.unwrap_or(0)
+		}
+	}
+	pub fn set_property(&mut self, property: MapProperty, value: Option<i32>) -> Result<(), String>
+	{
+		let block = match (property, value)
+		{
+			(MapProperty::SideBlock | MapProperty::EdgeBlock, Some(value)) if value < 0 || value >= block::BLOCKS.len() as i32 => return Err("Invalid block ID.".to_string()),
+			(MapProperty::ViewDistance, Some(value)) if value < 0 => return Err("The view distance cannot be negative.".to_string()),
+			(_, value) => value.map(|value| value as u8)
+		};
+		match property
+		{
+			MapProperty::SideBlock => self.side_block = block,
+			MapProperty::EdgeBlock => self.edge_block = block,
+			MapProperty::EdgeHeight => self.edge_height = value,
+			MapProperty::CloudsHeight => self.clouds_height = value,
+			MapProperty::ViewDistance => self.view_distance = value
+		}
+		Ok(())
+	}
+	// everything a client needs to be told about the environment. the server leaves out what it does not support.
+	pub fn packets(&self, size_y: i16) -> Vec<Packet>
+	{
+		let mut packets = Vec::new();
+		for (i, color) in EnvColor::ALL.into_iter().enumerate()
+		{
+			let (r, g, b) = match self.color(color)
+			{
+				Some([r, g, b]) => (r as i16, g as i16, b as i16),
+				None => (-1, -1, -1) // back to the client's default
+			};
+			packets.push(Packet::EnvSetColor { variable: i as u8, r, g, b });
+		}
+		packets.push(Packet::EnvSetWeatherType { weather: self.weather as u8 });
+		packets.push(Packet::SetMapEnvUrl { url: self.texture_url.clone() });
+		for (i, property) in MapProperty::ALL.into_iter().enumerate()
+		{
+			packets.push(Packet::SetMapEnvProperty { property: i as u8, value: self.property(property, size_y) });
+		}
+		packets
+	}
+}
+
+// accepts colours like #ff8000 or ff8000
+pub fn parse_color(color: &str) -> Option<[u8; 3]>
+{
+	let hex = color.strip_prefix('#').unwrap_or(color);
+	if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit())
+	{
+		return None;
+	}
+	let value = u32::from_str_radix(hex, 16).ok()?;
+	Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
@@ -0,0 +1,244 @@
+use chrono::Local;
+use crate::chat::MessageType;
+use crate::profile::ProfileStore;
+use crate::server::Server;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::sync::Mutex;
+use tokio::time;
+
+const EVENT_QUEUE_SIZE: usize = 1024;
+const EVENTS_PER_LOCK: usize = 64; // events handled in one go before letting the rest of the server have the lock
+const TICK: Duration = Duration::from_millis(50);
+const BLOCKS_PER_TICK: usize = 2048;
+
+// everything a player does, handed to the game loop in the order it was received
+pub enum Event
+{
+	PlaceBlock { id: i8, x: i16, y: i16, z: i16, block: u8 },
+	Move { id: i8, x: i32, y: i32, z: i32, yaw: u8, pitch: u8 },
+	Message { id: i8, message: String },
+	Disconnected { id: i8 }
+}
+
+pub fn channel() -> (mpsc::Sender<Event>, mpsc::Receiver<Event>)
+{
+	mpsc::channel(EVENT_QUEUE_SIZE)
+}
+
+// work too big to be done at once. it gets a bit of every tick, so chat and movement keep going in between.
+pub trait Job: Send
+{
+	// the player who started the job, it is cancelled when they leave
+	fn owner(&self) -> i8;
+	// returns true once there is nothing left to do
+	fn run(&mut self, server: &mut Server, budget: usize) -> bool;
+}
+
+pub struct Cuboid
+{
+	id: i8,
+	min: (i16, i16, i16),
+	max: (i16, i16, i16),
+	block: u8,
+	done: usize
+}
+impl Cuboid
+{
+	pub fn new(id: i8, a: (i16, i16, i16), b: (i16, i16, i16), block: u8) -> Self
+	{
+		Cuboid { id, min: (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)), max: (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)), block, done: 0 }
+	}
+	fn volume(&self) -> usize
+	{
+		(self.max.0 - self.min.0 + 1) as usize * (self.max.1 - self.min.1 + 1) as usize * (self.max.2 - self.min.2 + 1) as usize
+	}
+}
+impl Job for Cuboid
+{
+	fn owner(&self) -> i8
+	{
+		self.id
+	}
+	fn run(&mut self, server: &mut Server, budget: usize) -> bool
+	{
+		let (size_y, size_z) = ((self.max.1 - self.min.1 + 1) as usize, (self.max.2 - self.min.2 + 1) as usize);
+		let end = self.volume().min(self.done + budget);
+		for i in self.done..end
+		{
+			let x = self.min.0 + (i / (size_y * size_z)) as i16;
+			let y = self.min.1 + (i / size_z % size_y) as i16;
+			let z = self.min.2 + (i % size_z) as i16;
+			server.set_block(self.id, x, y, z, self.block, false);
+		}
+		self.done = end;
+		if self.done < self.volume()
+		{
+			server.send_message_type(self.id, MessageType::BottomRight1, &format!("&eCuboid: {}%", self.done * 100 / self.volume()));
+			return false;
+		}
+		server.send_message_type(self.id, MessageType::BottomRight1, "");
+		server.send_message(-1, self.id, &format!("Cuboid done, {} blocks changed.", self.volume()));
+		true
+	}
+}
+
+// compresses and writes the level and profiles on a blocking thread, so the game keeps going while saving
+pub struct Save
+{
+	id: i8,
+	result: Option<oneshot::Receiver<Saved>>
+}
+struct Saved
+{
+	level: Result<(), String>,
+	profiles: Result<(), String>
+}
+impl Save
+{
+	pub fn new(id: i8) -> Self
+	{
+		Save { id, result: None }
+	}
+	fn done(&self, server: &mut Server, saved: Saved)
+	{
+		if saved.profiles.is_err()
+		{
+			server.profiles.changed = true;
+		}
+		for e in [saved.level, saved.profiles].into_iter().filter_map(Result::err)
+		{
+			println!("{}", e);
+			server.send_message(-1, self.id, &e);
+		}
+		server.broadcast_message(-1, "Done.");
+		server.broadcast_message_type(MessageType::Status2, &format!("&eWorld saved at {}", Local::now().format("%H:%M")));
+	}
+}
+impl Job for Save
+{
+	// the level still has to be saved when whoever asked for it leaves
+	fn owner(&self) -> i8
+	{
+		-1
+	}
+	fn run(&mut self, server: &mut Server, _: usize) -> bool
+	{
+		let Some(result) = &mut self.result else
+		{
+			let (level, profiles) = (server.level.take_save(), server.profiles.take_save());
+			if level.is_none() && profiles.is_none()
+			{
+				self.done(server, Saved { level: Ok(()), profiles: Ok(()) });
+				return true;
+			}
+			let (send, recv) = oneshot::channel();
+			tokio::task::spawn_blocking(move ||
+			{
+				let level = level.map_or(Ok(()), |level| level.write());
+				let profiles = profiles.map_or(Ok(()), ProfileStore::write);
+				let _ = send.send(Saved { level, profiles });
+			});
+			self.result = Some(recv);
+			return false;
+		};
+		match result.try_recv()
+		{
+			Err(oneshot::error::TryRecvError::Empty) => false,
+			Err(oneshot::error::TryRecvError::Closed) =>
+			{
+				server.profiles.changed = true;
+				self.done(server, Saved { level: Err("saving stopped halfway".to_string()), profiles: Ok(()) });
+				true
+			}
+			Ok(saved) =>
+			{
+				self.done(server, saved);
+				true
+			}
+		}
+	}
+}
+
+// a bug in one event or command should not stop the game for everyone
+fn guarded(what: &str, f: impl FnOnce())
+{
+	if std::panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+	{
+		println!("the game loop recovered from a panic while {}", what);
+	}
+}
+
+fn handle(server: &mut Server, event: Event)
+{
+	if let Event::PlaceBlock { id, .. } | Event::Move { id, .. } | Event::Message { id, .. } = event
+	{
+		if server.is_kicked(id)
+		{
+			return;
+		}
+	}
+	match event
+	{
+		Event::PlaceBlock { id, x, y, z, block } => server.set_block(id, x, y, z, block, true),
+		Event::Move { id, x, y, z, yaw, pitch } => server.move_player(id, id, x, y, z, yaw, pitch),
+		Event::Message { id, message } =>
+		{
+			if let Some(command) = message.strip_prefix('/')
+			{
+				let mut split = command.split(' ');
+				let command = split.next().unwrap_or_default().to_lowercase();
+				server.command(id, command, split.collect());
+			}
+			else
+			{
+				server.chat(id, message);
+			}
+		}
+		Event::Disconnected { id } => server.disconnected(id)
+	}
+}
+
+// the only place where what players do touches the server, so everything from one client happens in order
+pub async fn game_loop(server: Arc<Mutex<Server>>, mut events: mpsc::Receiver<Event>)
+{
+	let mut interval = time::interval(TICK);
+	interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+	loop
+	{
+		tokio::select!
+		{
+			event = events.recv() =>
+			{
+				let Some(event) = event else { break; };
+				let mut server = server.lock().await;
+				guarded("handling an event", || handle(&mut server, event));
+				for _ in 1..EVENTS_PER_LOCK
+				{
+					match events.try_recv()
+					{
+						Ok(event) => guarded("handling an event", || handle(&mut server, event)),
+						Err(_) => break
+					}
+				}
+			}
+			_ = interval.tick() =>
+			{
+				let mut server = server.lock().await;
+				if !server.running
+				{
+					break;
+				}
+				guarded("ticking", ||
+				{
+					server.run_jobs(BLOCKS_PER_TICK);
+					server.broadcast_movement();
+					server.resync_positions();
+				});
+			}
+		}
+	}
+}
@@ -0,0 +1,70 @@
+use crate::packet::Packet;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+fn default_allowed() -> bool { true }
+
+// what clients with HackControl let their players do in a level. everything is allowed by default.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Hacks
+{
+	#[serde(default = "default_allowed")]
+	pub flying: bool,
+	#[serde(default = "default_allowed")]
+	pub noclip: bool,
+	#[serde(default = "default_allowed")]
+	pub speeding: bool,
+	#[serde(default = "default_allowed")]
+	pub spawn_control: bool, // respawning and setting your own spawn point
+	#[serde(default = "default_allowed")]
+	pub third_person: bool,
+	#[serde(default)]
+	pub jump_height: Option<i16> // in player units, None for the normal height
+}
+impl Default for Hacks
+{
+	fn default() -> Self
+	{
+		Hacks { flying: true, noclip: true, speeding: true, spawn_control: true, third_person: true, jump_height: None }
+	}
+}
+impl Hacks
+{
+	pub const NAMES: [&str; 5] = ["flying", "noclip", "speeding", "respawn", "thirdperson"];
+	pub fn get(&self, name: &str) -> Option<bool>
+	{
+		match name.to_lowercase().as_str()
+		{
+			"flying" => Some(self.flying),
+			"noclip" => Some(self.noclip),
+			"speeding" => Some(self.speeding),
+			"respawn" => Some(self.spawn_control),
+			"thirdperson" => Some(self.third_person),
+			_ => None
+		}
+	}
+	pub fn get_mut(&mut self, name: &str) -> Option<&mut bool>
+	{
+		match name.to_lowercase().as_str()
+		{
+			"flying" => Some(&mut self.flying),
+			"noclip" => Some(&mut self.noclip),
+			"speeding" => Some(&mut self.speeding),
+			"respawn" => Some(&mut self.spawn_control),
+			"thirdperson" => Some(&mut self.third_person),
+			_ => None
+		}
+	}
+	pub fn packet(&self) -> Packet
+	{
+		Packet::HackControl
+		{
+			flying: self.flying,
+			noclip: self.noclip,
+			speeding: self.speeding,
+			spawn_control: self.spawn_control,
+			third_person: self.third_person,
+			jump_height: self.jump_height.unwrap_or(-1)
+		}
+	}
+}
@@ -17,7 +17,10 @@ pub trait AsyncReadClassicExt: AsyncRead
 {
 	async fn read_string(&mut self) -> Result<String, std::io::Error>;
 	async fn read_array(&mut self) -> Result<Vec<u8>, std::io::Error>;
+	async fn read_coord(&mut self, extended: bool) -> Result<i32, std::io::Error>;
 	async fn read_packet(&mut self) -> Result<Packet, std::io::Error>;
+	// with ExtEntityPositions, entity coordinates are sent as i32 instead of i16
+	async fn read_packet_with(&mut self, extended_positions: bool) -> Result<Packet, std::io::Error>;
 }
 #[async_trait]
 impl<R: AsyncRead + Unpin + Send> AsyncReadClassicExt for R
@@ -44,7 +47,15 @@ impl<R: AsyncRead + Unpin + Send> AsyncReadClassicExt for R
 		self.read_exact(&mut buffer).await?;
 		Ok(buffer)
 	}
+	async fn read_coord(&mut self, extended: bool) -> Result<i32, std::io::Error>
+	{
+		if extended { self.read_i32().await } else { Ok(self.read_i16().await? as i32) }
+	}
 	async fn read_packet(&mut self) -> Result<Packet, std::io::Error>
+	{
+		self.read_packet_with(false).await
+	}
+	async fn read_packet_with(&mut self, extended_positions: bool) -> Result<Packet, std::io::Error>
 	{
 		let id = self.read_u8().await?;
 		match id
@@ -89,18 +100,18 @@ impl<R: AsyncRead + Unpin + Send> AsyncReadClassicExt for R
 				{
 					id: self.read_i8().await?,
 					name: self.read_string().await?,
-					x: self.read_i16().await?,
-					y: self.read_i16().await?,
-					z: self.read_i16().await?,
+					x: self.read_coord(extended_positions).await?,
+					y: self.read_coord(extended_positions).await?,
+					z: self.read_coord(extended_positions).await?,
 					yaw: self.read_u8().await?,
 					pitch: self.read_u8().await?
 				}),
 			0x08 => Ok(Packet::SetPosAndLook
 				{
 					id: self.read_i8().await?,
-					x: self.read_i16().await?,
-					y: self.read_i16().await?,
-					z: self.read_i16().await?,
+					x: self.read_coord(extended_positions).await?,
+					y: self.read_coord(extended_positions).await?,
+					z: self.read_coord(extended_positions).await?,
 					yaw: self.read_u8().await?,
 					pitch: self.read_u8().await?
 				}),
@@ -143,6 +154,16 @@ impl<R: AsyncRead + Unpin + Send> AsyncReadClassicExt for R
 				{
 					user_mode: self.read_u8().await?
 				}),
+			0x10 => Ok(Packet::ExtInfo
+				{
+					app_name: self.read_string().await?,
+					extension_count: self.read_i16().await?
+				}),
+			0x11 => Ok(Packet::ExtEntry
+				{
+					name: self.read_string().await?,
+					version: self.read_i32().await?
+				}),
 			_ => Ok(Packet::Unknown { id })
 		}
 	}
@@ -152,7 +173,9 @@ pub trait AsyncWriteClassicExt: AsyncWrite
 {
 	async fn write_string(&mut self, v: String) -> Result<(), std::io::Error>;
 	async fn write_array(&mut self, v: Vec<u8>) -> Result<(), std::io::Error>;
+	async fn write_coord(&mut self, v: i32, extended: bool) -> Result<(), std::io::Error>;
 	async fn write_packet(&mut self, v: Packet) -> Result<(), std::io::Error>;
+	async fn write_packet_with(&mut self, v: Packet, extended_positions: bool) -> Result<(), std::io::Error>;
 }
 #[async_trait]
 impl<W: AsyncWrite + Unpin + Send> AsyncWriteClassicExt for W
@@ -204,7 +227,16 @@ impl<W: AsyncWrite + Unpin + Send> AsyncWriteClassicExt for W
 		assert!(v.len() == ARRAY_LEN);
 		return self.write_all(&v).await;
 	}
-	async fn write_packet(&mut self, v: Packet) -> Result<(), std::io::Error> {
+	async fn write_coord(&mut self, v: i32, extended: bool) -> Result<(), std::io::Error>
+	{
+		// clients without the extension cannot be in a level this large, so clamping only affects the out of bounds
+		if extended { self.write_i32(v).await } else { self.write_i16(v.clamp(i16::MIN as i32, i16::MAX as i32) as i16).await }
+	}
+	async fn write_packet(&mut self, v: Packet) -> Result<(), std::io::Error>
+	{
+		self.write_packet_with(v, false).await
+	}
+	async fn write_packet_with(&mut self, v: Packet, extended_positions: bool) -> Result<(), std::io::Error> {
 		match v {
 			Packet::Identification {
 				protocol,
@@ -262,9 +294,9 @@ impl<W: AsyncWrite + Unpin + Send> AsyncWriteClassicExt for W
 				self.write_u8(0x07).await?;
 				self.write_i8(id).await?;
 				self.write_string(name).await?;
-				self.write_i16(x).await?;
-				self.write_i16(y).await?;
-				self.write_i16(z).await?;
+				self.write_coord(x, extended_positions).await?;
+				self.write_coord(y, extended_positions).await?;
+				self.write_coord(z, extended_positions).await?;
 				self.write_u8(yaw).await?;
 				self.write_u8(pitch).await?;
 				Ok(())
@@ -272,9 +304,9 @@ impl<W: AsyncWrite + Unpin + Send> AsyncWriteClassicExt for W
 			Packet::SetPosAndLook { id, x, y, z, yaw, pitch, } => {
 				self.write_u8(0x08).await?;
 				self.write_i8(id).await?;
-				self.write_i16(x).await?;
-				self.write_i16(y).await?;
-				self.write_i16(z).await?;
+				self.write_coord(x, extended_positions).await?;
+				self.write_coord(y, extended_positions).await?;
+				self.write_coord(z, extended_positions).await?;
 				self.write_u8(yaw).await?;
 				self.write_u8(pitch).await?;
 				Ok(())
@@ -325,6 +357,83 @@ impl<W: AsyncWrite + Unpin + Send> AsyncWriteClassicExt for W
 				self.write_u8(user_mode).await?;
 				Ok(())
 			}
+			Packet::ExtInfo { app_name, extension_count } => {
+				self.write_u8(0x10).await?;
+				self.write_string(app_name).await?;
+				self.write_i16(extension_count).await?;
+				Ok(())
+			}
+			Packet::ExtEntry { name, version } => {
+				self.write_u8(0x11).await?;
+				self.write_string(name).await?;
+				self.write_i32(version).await?;
+				Ok(())
+			}
+			Packet::LevelStartFastMap { volume } => {
+				self.write_u8(0x02).await?;
+				self.write_i32(volume).await?;
+				Ok(())
+			}
+			Packet::ExtAddPlayerName { name_id, player_name, list_name, group_name, group_rank } => {
+				self.write_u8(0x16).await?;
+				self.write_i16(name_id).await?;
+				self.write_string(player_name).await?;
+				self.write_string(list_name).await?;
+				self.write_string(group_name).await?;
+				self.write_u8(group_rank).await?;
+				Ok(())
+			}
+			Packet::ExtRemovePlayerName { name_id } => {
+				self.write_u8(0x18).await?;
+				self.write_i16(name_id).await?;
+				Ok(())
+			}
+			Packet::EnvSetColor { variable, r, g, b } => {
+				self.write_u8(0x19).await?;
+				self.write_u8(variable).await?;
+				self.write_i16(r).await?;
+				self.write_i16(g).await?;
+				self.write_i16(b).await?;
+				Ok(())
+			}
+			Packet::EnvSetWeatherType { weather } => {
+				self.write_u8(0x1f).await?;
+				self.write_u8(weather).await?;
+				Ok(())
+			}
+			Packet::SetMapEnvUrl { url } => {
+				self.write_u8(0x28).await?;
+				self.write_string(url).await?;
+				Ok(())
+			}
+			Packet::SetMapEnvProperty { property, value } => {
+				self.write_u8(0x29).await?;
+				self.write_u8(property).await?;
+				self.write_i32(value).await?;
+				Ok(())
+			}
+			Packet::HackControl { flying, noclip, speeding, spawn_control, third_person, jump_height } => {
+				self.write_u8(0x20).await?;
+				self.write_u8(flying as u8).await?;
+				self.write_u8(noclip as u8).await?;
+				self.write_u8(speeding as u8).await?;
+				self.write_u8(spawn_control as u8).await?;
+				self.write_u8(third_person as u8).await?;
+				self.write_i16(jump_height).await?;
+				Ok(())
+			}
+			Packet::ExtAddEntity2 { id, name, skin, x, y, z, yaw, pitch } => {
+				self.write_u8(0x21).await?;
+				self.write_i8(id).await?;
+				self.write_string(name).await?;
+				self.write_string(skin).await?;
+				self.write_coord(x, extended_positions).await?;
+				self.write_coord(y, extended_positions).await?;
+				self.write_coord(z, extended_positions).await?;
+				self.write_u8(yaw).await?;
+				self.write_u8(pitch).await?;
+				Ok(())
+			}
 			_ => Err(std::io::Error::new(std::io::ErrorKind::Other, "tried to send unknown packet")),
 		}
 	}
@@ -0,0 +1,262 @@
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+// a block of addresses in CIDR notation, e.g. 192.168.0.0/16 or 2001:db8::/32
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpRange
+{
+	addr: IpAddr,
+	prefix: u8
+}
+impl IpRange
+{
+	pub fn new(addr: IpAddr, prefix: u8) -> Option<Self>
+	{
+		let addr = addr.to_canonical();
+		match addr
+		{
+			IpAddr::V4(v4) if prefix <= 32 => Some(IpRange { addr: IpAddr::V4(Ipv4Addr::from(u32::from(v4) & v4_mask(prefix))), prefix }),
+			IpAddr::V6(v6) if prefix <= 128 => Some(IpRange { addr: IpAddr::V6(Ipv6Addr::from(u128::from(v6) & v6_mask(prefix))), prefix }),
+			_ => None
+		}
+	}
+	pub fn contains(&self, ip: &IpAddr) -> bool
+	{
+		match (self.addr, ip.to_canonical())
+		{
+			(IpAddr::V4(addr), IpAddr::V4(ip)) => u32::from(ip) & v4_mask(self.prefix) == u32::from(addr),
+			(IpAddr::V6(addr), IpAddr::V6(ip)) => u128::from(ip) & v6_mask(self.prefix) == u128::from(addr),
+			_ => false
+		}
+	}
+}
+// loopback and private networks, where players are usually not coming from a server list
+pub fn is_lan(ip: &IpAddr) -> bool
+{
+	const LAN: [&str; 6] = ["127.0.0.0/8", "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "::1/128", "fc00::/7"];
+	LAN.iter().filter_map(|range| range.parse::<IpRange>().ok()).any(|range| range.contains(ip))
+}
+fn v4_mask(prefix: u8) -> u32
+{
+	u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+fn v6_mask(prefix: u8) -> u128
+{
+	u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+impl FromStr for IpRange
+{
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err>
+	{
+		let (addr, prefix) = match s.split_once('/')
+		{
+			Some((addr, prefix)) => (addr, Some(prefix)),
+			None => (s, None)
+		};
+		let addr = addr.parse::<IpAddr>().map_err(|_| format!("{} is not a valid ip address", addr))?;
+		let prefix = match prefix
+		{
+			Some(prefix) => prefix.parse::<u8>().map_err(|_| format!("{} is not a valid prefix length", prefix))?,
+			None => if addr.to_canonical().is_ipv4() { 32 } else { 128 }
+		};
+		IpRange::new(addr, prefix).ok_or_else(|| format!("/{} is too long of a prefix", prefix))
+	}
+}
+impl fmt::Display for IpRange
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		write!(f, "{}/{}", self.addr, self.prefix)
+	}
+}
+impl TryFrom<String> for IpRange
+{
+	type Error = String;
+	fn try_from(s: String) -> Result<Self, Self::Error>
+	{
+		s.parse()
+	}
+}
+impl From<IpRange> for String
+{
+	fn from(range: IpRange) -> Self
+	{
+		range.to_string()
+	}
+}
+
+// lookup structure for lists of addresses and ranges.
+// single addresses go in a hash set, ranges are merged into sorted intervals and binary searched.
+// two ranges either do not overlap or one is inside the other, so an interval usually holds a single range.
+#[derive(Default)]
+pub struct IpIndex
+{
+	ips: HashSet<IpAddr>,
+	v4: Vec<Interval<u32>>,
+	v6: Vec<Interval<u128>>
+}
+struct Interval<T>
+{
+	start: T,
+	end: T,
+	ranges: Vec<(T, T, usize)> // every range merged into this interval, with its position in the list
+}
+impl IpIndex
+{
+	pub fn new<'a>(ips: impl Iterator<Item = &'a IpAddr>, ranges: impl Iterator<Item = &'a IpRange>) -> Self
+	{
+		let mut v4 = Vec::new();
+		let mut v6 = Vec::new();
+		for (i, range) in ranges.enumerate()
+		{
+			match range.addr
+			{
+				IpAddr::V4(addr) =>
+				{
+					let start = u32::from(addr);
+					v4.push((start, start | !v4_mask(range.prefix), i));
+				}
+				IpAddr::V6(addr) =>
+				{
+					let start = u128::from(addr);
+					v6.push((start, start | !v6_mask(range.prefix), i));
+				}
+			}
+		}
+		IpIndex { ips: ips.map(|ip| ip.to_canonical()).collect(), v4: merge(v4), v6: merge(v6) }
+	}
+	pub fn contains(&self, ip: &IpAddr) -> bool
+	{
+		self.ips.contains(&ip.to_canonical()) || !self.ranges(ip).is_empty()
+	}
+	// positions of the ranges the address is in, the smallest range first
+	pub fn ranges(&self, ip: &IpAddr) -> Vec<usize>
+	{
+		match ip.to_canonical()
+		{
+			IpAddr::V4(ip) => search(&self.v4, u32::from(ip)),
+			IpAddr::V6(ip) => search(&self.v6, u128::from(ip))
+		}
+	}
+}
+fn merge<T: Ord + Copy>(mut ranges: Vec<(T, T, usize)>) -> Vec<Interval<T>>
+{
+	// the bigger of two ranges starting at the same address comes first, so it opens the interval
+	ranges.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+	let mut merged: Vec<Interval<T>> = Vec::with_capacity(ranges.len());
+	for (start, end, i) in ranges
+	{
+		if let Some(last) = merged.last_mut()
+		{
+			if start <= last.end
+			{
+				last.end = last.end.max(end);
+				last.ranges.push((start, end, i));
+				continue;
+			}
+		}
+		merged.push(Interval { start, end, ranges: vec![(start, end, i)] });
+	}
+	merged
+}
+fn search<T: Ord + Copy>(intervals: &[Interval<T>], value: T) -> Vec<usize>
+{
+	let i = intervals.partition_point(|interval| interval.start <= value);
+	if i == 0 || value > intervals[i - 1].end
+	{
+		return Vec::new();
+	}
+	// ranges further in are inside the ones before them
+	intervals[i - 1].ranges.iter().rev().filter(|(start, end, _)| *start <= value && value <= *end).map(|(_, _, i)| *i).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn ip(s: &str) -> IpAddr
+	{
+		s.parse().unwrap()
+	}
+	fn range(s: &str) -> IpRange
+	{
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn parses_and_masks_ranges()
+	{
+		assert_eq!(range("192.168.5.77/16").to_string(), "192.168.0.0/16");
+		assert_eq!(range("10.1.2.3").to_string(), "10.1.2.3/32");
+		assert_eq!(range("2001:db8::1").to_string(), "2001:db8::1/128");
+		assert_eq!(range("2001:db8:ffff::/32").to_string(), "2001:db8::/32");
+		assert_eq!(range("::ffff:10.1.2.3/8").to_string(), "10.0.0.0/8");
+		assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+		assert!("::/129".parse::<IpRange>().is_err());
+		assert!("10.0.0/8".parse::<IpRange>().is_err());
+		assert!("10.0.0.0/x".parse::<IpRange>().is_err());
+	}
+
+	#[test]
+	fn prefix_edges()
+	{
+		let everything = range("1.2.3.4/0");
+		assert_eq!(everything.to_string(), "0.0.0.0/0");
+		assert!(everything.contains(&ip("255.255.255.255")));
+		assert!(everything.contains(&ip("::ffff:8.8.8.8")));
+		assert!(!everything.contains(&ip("2001:db8::1")));
+		let single = range("1.2.3.4/32");
+		assert!(single.contains(&ip("1.2.3.4")));
+		assert!(!single.contains(&ip("1.2.3.5")));
+		assert!(range("::/0").contains(&ip("2001:db8::1")));
+	}
+
+	#[test]
+	fn mapped_addresses_match_ipv4_ranges()
+	{
+		assert!(range("192.168.0.0/16").contains(&ip("::ffff:192.168.1.1")));
+		assert!(is_lan(&ip("::ffff:127.0.0.1")));
+		assert!(is_lan(&ip("::1")));
+		assert!(!is_lan(&ip("8.8.8.8")));
+	}
+
+	#[test]
+	fn index_finds_addresses_and_ranges()
+	{
+		let ips = [ip("::ffff:1.1.1.1"), ip("2001:db8::5")];
+		let ranges = [range("10.0.0.0/8"), range("10.1.0.0/16"), range("10.1.2.0/24"), range("172.16.0.0/12"), range("0.0.0.0/32"), range("255.255.255.255/32")];
+		let index = IpIndex::new(ips.iter(), ranges.iter());
+		assert!(index.contains(&ip("1.1.1.1")));
+		assert!(index.contains(&ip("2001:db8::5")));
+		assert!(!index.contains(&ip("2001:db8::6")));
+		// nested ranges come back smallest first
+		assert_eq!(index.ranges(&ip("10.1.2.3")), vec![2, 1, 0]);
+		assert_eq!(index.ranges(&ip("10.1.3.3")), vec![1, 0]);
+		assert_eq!(index.ranges(&ip("::ffff:10.200.0.1")), vec![0]);
+		assert_eq!(index.ranges(&ip("172.31.255.255")), vec![3]);
+		assert_eq!(index.ranges(&ip("0.0.0.0")), vec![4]);
+		assert_eq!(index.ranges(&ip("255.255.255.255")), vec![5]);
+		assert!(index.ranges(&ip("11.0.0.0")).is_empty());
+		assert!(index.ranges(&ip("9.255.255.255")).is_empty());
+		assert!(!index.contains(&ip("0.0.0.1")));
+	}
+
+	#[test]
+	fn index_merges_ranges_with_the_same_start()
+	{
+		let ranges = [range("10.0.0.0/24"), range("10.0.0.0/8"), range("10.0.0.0/16")];
+		let index = IpIndex::new([].iter(), ranges.iter());
+		assert_eq!(index.v4.len(), 1);
+		assert_eq!(index.ranges(&ip("10.0.0.1")), vec![0, 2, 1]);
+		assert_eq!(index.ranges(&ip("10.0.1.1")), vec![2, 1]);
+	}
+}
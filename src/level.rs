@@ -3,12 +3,15 @@ use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
 use chrono::Local;
 use crate::block;
+use crate::env::Environment;
+use crate::hacks::Hacks;
 use crate::noise::CombinedNoise;
 use crate::noise::Noise;
 use crate::noise::OctaveNoise;
 use crate::noise::PerlinNoise;
 use flate2::Compression;
 use flate2::read::GzDecoder;
+use flate2::write::DeflateEncoder;
 use flate2::write::GzEncoder;
 use rand::Rng;
 use rand::rngs::StdRng;
@@ -20,6 +23,8 @@ use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
 
 #[derive(Clone)]
 pub struct Level
@@ -28,14 +33,26 @@ pub struct Level
 	pub size_x: i16,
 	pub size_y: i16,
 	pub size_z: i16,
-	pub spawn_x: i16,
-	pub spawn_y: i16,
-	pub spawn_z: i16,
+	pub spawn_x: i32,
+	pub spawn_y: i32,
+	pub spawn_z: i32,
 	pub spawn_yaw: u8,
 	pub spawn_pitch: u8,
 	pub changed: bool,
+	pub revision: u64, // goes up with every block change
+	pub env: Environment,
+	pub hacks: Hacks,
 	data: Vec<u8>
 }
+// everything about a level besides its blocks, saved as json next to them
+#[derive(Serialize, Deserialize, Default)]
+struct Metadata
+{
+	#[serde(flatten)]
+	env: Environment,
+	#[serde(default)]
+	hacks: Hacks
+}
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub enum GenerationType
 {
@@ -43,11 +60,63 @@ pub enum GenerationType
 	Flat,
 	Vanilla
 }
+#[derive(Clone, Copy)]
 pub enum SaveType
 {
 	Network,
+	FastMap,
 	Disk
 }
+// compressed copies of the level as it is sent to clients, shared by everyone joining while it is unchanged
+#[derive(Default)]
+pub struct NetworkCache
+{
+	revision: u64,
+	gzip: Arc<OnceCell<Vec<u8>>>,
+	deflate: Arc<OnceCell<Vec<u8>>>
+}
+impl NetworkCache
+{
+	pub fn snapshot(&mut self, level: &Level, savetype: SaveType) -> LevelSnapshot
+	{
+		if self.revision != level.revision
+		{
+			*self = NetworkCache { revision: level.revision, ..Default::default() };
+		}
+		let data = match savetype
+		{
+			SaveType::FastMap => self.deflate.clone(),
+			_ => self.gzip.clone()
+		};
+		let (size_x, size_y, size_z) = (level.size_x, level.size_y, level.size_z);
+		// only copy the blocks if someone still has to compress them
+		let level = if data.initialized() { None } else { Some(level.clone()) };
+		LevelSnapshot { size_x, size_y, size_z, savetype, data, level }
+	}
+}
+pub struct LevelSnapshot
+{
+	pub size_x: i16,
+	pub size_y: i16,
+	pub size_z: i16,
+	savetype: SaveType,
+	data: Arc<OnceCell<Vec<u8>>>,
+	level: Option<Level>
+}
+impl LevelSnapshot
+{
+	// compresses on a blocking thread, unless someone else already did it
+	pub async fn compressed(&mut self) -> Result<&Vec<u8>, String>
+	{
+		let level = self.level.take();
+		let savetype = self.savetype;
+		self.data.get_or_try_init(|| async move
+		{
+			let level = level.ok_or("level snapshot is missing")?;
+			tokio::task::spawn_blocking(move || level.get_compressed(savetype)).await.map_err(|e| e.to_string())?
+		}).await
+	}
+}
 // A level implements everything that's permanently saved into a level.
 // This means its size, and the blocks inside.
 impl Level
@@ -65,7 +134,10 @@ impl Level
 			spawn_z: 0,
 			spawn_yaw: 0,
 			spawn_pitch: 0,
-			changed: false
+			changed: false,
+			revision: 0,
+			env: Environment::default(),
+			hacks: Hacks::default()
 		}
 	}
 	pub fn generate(&mut self, size_x: i16, size_y: i16, size_z: i16, gen_type: GenerationType, seed: u64) -> Result<(), String>
@@ -408,7 +480,21 @@ impl Level
 	}
 	pub fn load(&mut self) -> Result<(), String>
 	{
-		self.load_from(format!("{}.dat", self.name))
+		self.load_from(format!("{}.dat", self.name))?;
+		// the metadata is optional, levels without it just look like every other
+		if let Ok(file) = File::open(format!("{}.json", self.name))
+		{
+			match serde_json::from_reader::<_, Metadata>(file)
+			{
+				Ok(metadata) =>
+				{
+					self.env = metadata.env;
+					self.hacks = metadata.hacks;
+				}
+				Err(e) => println!("could not read {}.json: {}", self.name, e)
+			}
+		}
+		Ok(())
 	}
 	pub fn save_to(&self, path: String) -> Result<(), String>
 	{
@@ -422,21 +508,38 @@ impl Level
 		}
 		Err(format!("could not save level {}", path))
 	}
+	// takes a copy of the level if it changed since the last save, so it can be written without holding up the server
+	pub fn take_save(&mut self) -> Option<Level>
+	{
+		if !self.changed
+		{
+			return None;
+		}
+		self.changed = false;
+		Some(self.clone())
+	}
 	pub fn save(&mut self) -> Result<(), String>
 	{
-		if self.changed
+		match self.take_save()
 		{
-			self.changed = false;
-			if self.copy_backup().is_err()
-			{
-				println!("could not backup previous world.");
-			}
-			self.save_to(format!("{}.dat", self.name))
+			Some(level) => level.write(),
+			None => Ok(())
 		}
-		else
+	}
+	// writes the level and its metadata, after backing up what was saved before
+	pub fn write(&self) -> Result<(), String>
+	{
+		if self.copy_backup().is_err()
+		{
+			println!("could not backup previous world.");
+		}
+		let metadata = Metadata { env: self.env.clone(), hacks: self.hacks.clone() };
+		let json = serde_json::to_string_pretty(&metadata).map_err(|e| e.to_string())?;
+		if fs::write(format!("{}.json", self.name), json).is_err()
 		{
-			Ok(())
+			println!("could not save {}.json", self.name);
 		}
+		self.save_to(format!("{}.dat", self.name))
 	}
 	pub fn copy_backup(&self) -> Result<u64, std::io::Error>
 	{
@@ -456,6 +559,7 @@ impl Level
 		{
 			self.changed = true;
 		}
+		self.revision += 1;
 		self.data[x as usize + z as usize * self.size_x as usize + y as usize * self.size_x as usize * self.size_z as usize] = b;
 	}
 	pub fn place_block(&mut self, x: i16, mut y: i16, z: i16, mut b: u8) -> Vec<(i16,i16,i16,u8)>
@@ -702,16 +806,37 @@ impl Level
 		}
 		self.set_block(x, y, z, 17);
 	}
+	pub fn get_compressed(&self, savetype: SaveType) -> Result<Vec<u8>, String>
+	{
+		match savetype
+		{
+			SaveType::FastMap => self.get_deflate(),
+			_ => self.get_gzip(savetype)
+		}
+	}
+	// FastMap sends the blocks as raw deflate, without the gzip header nor the length in front
+	pub fn get_deflate(&self) -> Result<Vec<u8>, String>
+	{
+		let mut e = DeflateEncoder::new(Vec::with_capacity(self.data.len() / 8), Compression::fast());
+		if e.write_all(&self.data).is_ok()
+		{
+			if let Ok(bytes) = e.finish()
+			{
+				return Ok(bytes);
+			}
+		}
+		Err(String::from("could not deflate world"))
+	}
 	pub fn get_gzip(&self, savetype: SaveType) -> Result<Vec<u8>, String>
 	{
 		let mut e = GzEncoder::new(Vec::new(), match savetype
 		{
-			SaveType::Network => Compression::fast(),
+			SaveType::Network | SaveType::FastMap => Compression::fast(),
 			SaveType::Disk => Compression::default()
 		});
 		let ok = match savetype
 		{
-			SaveType::Network =>
+			SaveType::Network | SaveType::FastMap =>
 			{
 				e.write_u32::<NetworkEndian>(self.size_x as u32 * self.size_y as u32 * self.size_z as u32).is_ok()
 			}
@@ -733,21 +858,26 @@ impl Level
 		}
 		Err(String::from("could not gzip world"))
 	}
-	pub fn max_y(&mut self, x: i16, z: i16) -> i16
+	pub fn max_y(&mut self, x: i16, z: i16) -> i32
 	{
 		for y in (0..self.size_y).rev()
 		{
 			if self.get_block(x, y, z) > 0
 			{
-				return y*32+61;
+				return y as i32*32+61;
 			}
 		}
 		29
 	}
+	// whether positions in the level can go past what fits in the coordinates of clients without ExtEntityPositions
+	pub fn needs_extended_positions(&self) -> bool
+	{
+		self.size_x.max(self.size_y).max(self.size_z) as i32 * 32 + 64 > i16::MAX as i32
+	}
 	pub fn reset_spawn(&mut self)
 	{
-		self.spawn_x = self.size_x*16 + 16;
-		self.spawn_z = self.size_z*16 + 16;
+		self.spawn_x = self.size_x as i32*16 + 16;
+		self.spawn_z = self.size_z as i32*16 + 16;
 		self.spawn_y = self.max_y(self.size_x/2, self.size_z/2);
 	}
 }
\ No newline at end of file
@@ -3,10 +3,17 @@ mod chat;
 mod client;
 mod command;
 mod config;
+mod cpe;
+mod env;
+mod game;
+mod hacks;
 mod io;
+mod iprange;
 mod level;
 mod noise;
 mod packet;
+mod profile;
+mod queue;
 mod server;
 mod userdata;
 
@@ -43,6 +50,8 @@ async fn main()
 	}
 	let server = Arc::new(Mutex::new(Server::new(config, level)));
 	Server::start_ticks(&server).await;
+	let (events, events_recv) = game::channel();
+	tokio::spawn(game::game_loop(server.clone(), events_recv));
 	let s = server.clone();
 	println!("ready");
 	tokio::select! 
@@ -52,7 +61,7 @@ async fn main()
 			while let Ok((stream, ip)) = listener.accept().await
 			{
 				let server = Arc::clone(&s);
-				tokio::spawn(Client::handle_client(stream, ip, server));
+				tokio::spawn(Client::handle_client(stream, ip, server, events.clone()));
 			}
 		} => {}
 		_ = signal::ctrl_c() => {}
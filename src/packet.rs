@@ -45,18 +45,18 @@ pub enum Packet
 	{
 		id: i8,
 		name: String,
-		x: i16,
-		y: i16,
-		z: i16,
+		x: i32,
+		y: i32,
+		z: i32,
 		yaw: u8,
 		pitch: u8
 	},
 	SetPosAndLook
 	{
 		id: i8,
-		x: i16,
-		y: i16,
-		z: i16,
+		x: i32,
+		y: i32,
+		z: i32,
 		yaw: u8,
 		pitch: u8
 	},
@@ -98,5 +98,75 @@ pub enum Packet
 	UpdateUserMode
 	{
 		user_mode: u8
+	},
+	ExtInfo
+	{
+		app_name: String,
+		extension_count: i16
+	},
+	ExtEntry
+	{
+		name: String,
+		version: i32
+	},
+	// FastMap's version of LevelStart, only sent by the server
+	LevelStartFastMap
+	{
+		volume: i32
+	},
+	// ExtPlayerList, adds or updates an entry of the tab list
+	ExtAddPlayerName
+	{
+		name_id: i16,
+		player_name: String,
+		list_name: String,
+		group_name: String,
+		group_rank: u8
+	},
+	ExtRemovePlayerName
+	{
+		name_id: i16
+	},
+	EnvSetColor
+	{
+		variable: u8,
+		r: i16,
+		g: i16,
+		b: i16
+	},
+	EnvSetWeatherType
+	{
+		weather: u8
+	},
+	// EnvMapAspect
+	SetMapEnvUrl
+	{
+		url: String
+	},
+	SetMapEnvProperty
+	{
+		property: u8,
+		value: i32
+	},
+	HackControl
+	{
+		flying: bool,
+		noclip: bool,
+		speeding: bool,
+		spawn_control: bool,
+		third_person: bool,
+		jump_height: i16
+	},
+	// ExtPlayerList's version of Spawn
+	ExtAddEntity2
+	{
+		id: i8,
+		name: String,
+		skin: String,
+		x: i32,
+		y: i32,
+		z: i32,
+		yaw: u8,
+		pitch: u8
 	}
 }
@@ -0,0 +1,317 @@
+use byteorder::NetworkEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Position
+{
+	pub x: i32,
+	pub y: i32,
+	pub z: i32,
+	pub yaw: u8,
+	pub pitch: u8
+}
+// everything that is kept about a player between sessions
+#[derive(Clone, Default)]
+pub struct Profile
+{
+	pub first_login: i64,
+	pub last_login: i64,
+	pub time_online: u64, // in seconds, not counting the current session
+	pub logins: u32,
+	pub blocks_placed: u64,
+	pub blocks_destroyed: u64,
+	pub messages_sent: u64,
+	pub positions: HashMap<String, Position>, // keyed by level name
+	pub nick: String, // empty when the player goes by their username
+	pub color: Option<char>,
+	pub title: String
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stat
+{
+	TimeOnline,
+	Logins,
+	BlocksPlaced,
+	BlocksDestroyed,
+	MessagesSent
+}
+impl Stat
+{
+	pub const ALL: [Stat; 5] = [Stat::TimeOnline, Stat::Logins, Stat::BlocksPlaced, Stat::BlocksDestroyed, Stat::MessagesSent];
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Stat::TimeOnline => "time",
+			Stat::Logins => "logins",
+			Stat::BlocksPlaced => "placed",
+			Stat::BlocksDestroyed => "destroyed",
+			Stat::MessagesSent => "messages"
+		}
+	}
+	pub fn from_name(name: &str) -> Option<Stat>
+	{
+		Stat::ALL.into_iter().find(|stat| stat.name().eq_ignore_ascii_case(name))
+	}
+	pub fn get(&self, profile: &Profile) -> u64
+	{
+		match self
+		{
+			Stat::TimeOnline => profile.time_online,
+			Stat::Logins => profile.logins as u64,
+			Stat::BlocksPlaced => profile.blocks_placed,
+			Stat::BlocksDestroyed => profile.blocks_destroyed,
+			Stat::MessagesSent => profile.messages_sent
+		}
+	}
+}
+
+fn read_string(r: &mut impl Read) -> Result<String, std::io::Error>
+{
+	let mut buffer = vec![0u8; r.read_u8()? as usize];
+	r.read_exact(&mut buffer)?;
+	String::from_utf8(buffer).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid string"))
+}
+fn write_string(w: &mut impl Write, v: &str) -> Result<(), std::io::Error>
+{
+	let bytes = &v.as_bytes()[..v.len().min(u8::MAX as usize)];
+	w.write_u8(bytes.len() as u8)?;
+	w.write_all(bytes)
+}
+
+impl Profile
+{
+	// fields are read in the order they were added. a record written by an older version simply
+	// ends early, and whatever is missing keeps its default value.
+	fn read(data: &[u8], version: u8) -> Profile
+	{
+		let mut profile = Profile::default();
+		let _ = profile.read_fields(&mut Cursor::new(data), version);
+		profile
+	}
+	fn read_fields(&mut self, r: &mut Cursor<&[u8]>, version: u8) -> Result<(), std::io::Error>
+	{
+		// positions were i16 before version 2
+		let read_coord = |r: &mut Cursor<&[u8]>| if version < 2 { r.read_i16::<NetworkEndian>().map(|v| v as i32) } else { r.read_i32::<NetworkEndian>() };
+		self.first_login = r.read_i64::<NetworkEndian>()?;
+		self.last_login = r.read_i64::<NetworkEndian>()?;
+		self.time_online = r.read_u64::<NetworkEndian>()?;
+		self.logins = r.read_u32::<NetworkEndian>()?;
+		self.blocks_placed = r.read_u64::<NetworkEndian>()?;
+		self.blocks_destroyed = r.read_u64::<NetworkEndian>()?;
+		self.messages_sent = r.read_u64::<NetworkEndian>()?;
+		for _ in 0..r.read_u8()?
+		{
+			let level = read_string(r)?;
+			let position = Position
+			{
+				x: read_coord(r)?,
+				y: read_coord(r)?,
+				z: read_coord(r)?,
+				yaw: r.read_u8()?,
+				pitch: r.read_u8()?
+			};
+			self.positions.insert(level, position);
+		}
+		self.nick = read_string(r)?;
+		self.color = char::from_u32(r.read_u8()? as u32).filter(|c| c.is_ascii_hexdigit());
+		self.title = read_string(r)?;
+		Ok(())
+	}
+	fn write(&self, w: &mut Vec<u8>) -> Result<(), std::io::Error>
+	{
+		w.write_i64::<NetworkEndian>(self.first_login)?;
+		w.write_i64::<NetworkEndian>(self.last_login)?;
+		w.write_u64::<NetworkEndian>(self.time_online)?;
+		w.write_u32::<NetworkEndian>(self.logins)?;
+		w.write_u64::<NetworkEndian>(self.blocks_placed)?;
+		w.write_u64::<NetworkEndian>(self.blocks_destroyed)?;
+		w.write_u64::<NetworkEndian>(self.messages_sent)?;
+		w.write_u8(self.positions.len().min(u8::MAX as usize) as u8)?;
+		for (level, position) in self.positions.iter().take(u8::MAX as usize)
+		{
+			write_string(w, level)?;
+			w.write_i32::<NetworkEndian>(position.x)?;
+			w.write_i32::<NetworkEndian>(position.y)?;
+			w.write_i32::<NetworkEndian>(position.z)?;
+			w.write_u8(position.yaw)?;
+			w.write_u8(position.pitch)?;
+		}
+		write_string(w, &self.nick)?;
+		w.write_u8(self.color.map_or(0, |c| c as u8))?;
+		write_string(w, &self.title)?;
+		Ok(())
+	}
+}
+
+// all the profiles are kept in memory and written out together as one gzipped file:
+// u8 version, u32 count, then for every player their name and a u32 length prefixed record.
+pub struct ProfileStore
+{
+	profiles: HashMap<String, Profile>,
+	pub changed: bool
+}
+impl ProfileStore
+{
+	const FILE: &str = "profiles.dat";
+	const VERSION: u8 = 3;
+
+	pub fn load() -> Self
+	{
+		let mut store = ProfileStore { profiles: HashMap::new(), changed: false };
+		if let Ok(mut file) = File::open(ProfileStore::FILE)
+		{
+			let mut buf = Vec::new();
+			if file.read_to_end(&mut buf).is_ok()
+			{
+				if let Err(e) = store.read(&mut GzDecoder::new(&buf[..]))
+				{
+					println!("could not read all profiles: {}", e);
+				}
+			}
+		}
+		println!("loaded {} player profiles", store.profiles.len());
+		store
+	}
+	fn read(&mut self, r: &mut impl Read) -> Result<(), std::io::Error>
+	{
+		let version = r.read_u8()?;
+		if version > ProfileStore::VERSION
+		{
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "profiles were saved by a newer version"));
+		}
+		for _ in 0..r.read_u32::<NetworkEndian>()?
+		{
+			let username = read_string(r)?;
+			// records got a u32 length in version 3, a u16 was too short for a player who visited many levels
+			let length = if version < 3 { r.read_u16::<NetworkEndian>()? as usize } else { r.read_u32::<NetworkEndian>()? as usize };
+			let mut record = vec![0u8; length];
+			r.read_exact(&mut record)?;
+			self.profiles.insert(username, Profile::read(&record, version));
+		}
+		Ok(())
+	}
+	// the file without its compression, which is cheap enough to make while the server is locked
+	pub fn take_save(&mut self) -> Option<Vec<u8>>
+	{
+		if !self.changed
+		{
+			return None;
+		}
+		let mut data = Vec::new();
+		let mut record = Vec::new();
+		data.push(ProfileStore::VERSION);
+		data.extend_from_slice(&(self.profiles.len() as u32).to_be_bytes());
+		for (username, profile) in &self.profiles
+		{
+			record.clear();
+			// writing into a vec cannot fail
+			let _ = profile.write(&mut record);
+			let _ = write_string(&mut data, username);
+			data.extend_from_slice(&(record.len() as u32).to_be_bytes());
+			data.extend_from_slice(&record);
+		}
+		self.changed = false;
+		Some(data)
+	}
+	// compresses and writes what take_save returned, so it can run on a blocking thread
+	pub fn write(data: Vec<u8>) -> Result<(), String>
+	{
+		let mut e = GzEncoder::new(Vec::new(), Compression::default());
+		e.write_all(&data).map_err(|_| "could not gzip profiles".to_string())?;
+		let bytes = e.finish().map_err(|_| "could not gzip profiles".to_string())?;
+		// write to a temporary file first so a crash never leaves half of the profiles behind
+		let temp = format!("{}.tmp", ProfileStore::FILE);
+		if fs::write(&temp, bytes).is_err() || fs::rename(&temp, ProfileStore::FILE).is_err()
+		{
+			return Err(format!("could not save {}", ProfileStore::FILE));
+		}
+		Ok(())
+	}
+	pub fn save(&mut self) -> Result<(), String>
+	{
+		let Some(data) = self.take_save() else { return Ok(()); };
+		let result = ProfileStore::write(data);
+		// tried again on the next save
+		self.changed |= result.is_err();
+		result
+	}
+	pub fn get(&self, username: &str) -> Option<&Profile>
+	{
+		self.profiles.get(username)
+	}
+	// whether anyone ever joined with this name, in any case
+	pub fn has_name(&self, name: &str) -> bool
+	{
+		self.profiles.keys().any(|username| username.eq_ignore_ascii_case(name))
+	}
+	pub fn get_mut(&mut self, username: &str) -> &mut Profile
+	{
+		self.changed = true;
+		self.profiles.entry(username.to_string()).or_default()
+	}
+	pub fn login(&mut self, username: &str)
+	{
+		let now = Utc::now().timestamp();
+		let profile = self.get_mut(username);
+		if profile.logins == 0
+		{
+			profile.first_login = now;
+		}
+		profile.last_login = now;
+		profile.logins += 1;
+	}
+	pub fn logout(&mut self, username: &str, level: &str, position: Position)
+	{
+		let now = Utc::now().timestamp();
+		let profile = self.get_mut(username);
+		profile.time_online += (now - profile.last_login).max(0) as u64;
+		profile.positions.insert(level.to_string(), position);
+	}
+	// the current session of players still online is counted by whoever calls this
+	pub fn top(&self, stat: Stat, count: usize, online: impl Fn(&str) -> u64) -> Vec<(&String, u64)>
+	{
+		let mut top: Vec<(&String, u64)> = self.profiles.iter().map(|(username, profile)| (username, stat.get(profile) + online(username))).collect();
+		top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+		top.truncate(count);
+		top
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn long_records_survive_saving()
+	{
+		let mut store = ProfileStore { profiles: HashMap::new(), changed: true };
+		let profile = store.get_mut("traveller");
+		profile.messages_sent = 7;
+		for i in 0..255
+		{
+			profile.positions.insert(format!("{:0>250}", i), Position { x: i, ..Default::default() });
+		}
+		profile.title = "t".repeat(255);
+		let data = store.take_save().unwrap();
+		assert!(store.take_save().is_none());
+		let mut loaded = ProfileStore { profiles: HashMap::new(), changed: false };
+		loaded.read(&mut &data[..]).unwrap();
+		let profile = loaded.get("traveller").unwrap();
+		assert_eq!(profile.messages_sent, 7);
+		assert_eq!(profile.positions.len(), 255);
+		assert_eq!(profile.title.len(), 255);
+	}
+}
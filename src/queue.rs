@@ -0,0 +1,451 @@
+use crate::packet::Packet;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::Notify;
+
+// what happens when a client cannot keep up with what the server sends it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum QueuePolicy
+{
+	Drop, // new packets are thrown away
+	Coalesce, // movement packets are thrown away first, since newer ones replace them anyway. kicks when there are none left
+	Kick // the client gets disconnected
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct QueueStats
+{
+	pub queued: usize,
+	pub peak: usize,
+	pub sent: u64,
+	pub coalesced: u64,
+	pub dropped: u64
+}
+
+struct QueueState
+{
+	packets: VecDeque<Packet>,
+	popped: u64, // how many packets ever left the queue, to turn sequence numbers into indices
+	moves: HashMap<i8, u64>, // sequence number of the last movement packet queued for each entity
+	droppable: usize, // movement and ping packets in the queue
+	closed: Option<String>,
+	senders: usize,
+	receiving: bool,
+	started: bool, // whether anything was taken out yet. until then the client is still getting the level
+	stats: QueueStats
+}
+
+struct PacketQueue
+{
+	state: Mutex<QueueState>,
+	notify: Notify,
+	size: usize,
+	policy: QueuePolicy
+}
+
+// a bounded packet queue, where movement updates for the same entity get merged together while waiting
+pub fn channel(size: usize, policy: QueuePolicy) -> (PacketSender, PacketReceiver)
+{
+	let queue = Arc::new(PacketQueue
+	{
+		state: Mutex::new(QueueState
+		{
+			packets: VecDeque::new(),
+			popped: 0,
+			moves: HashMap::new(),
+			droppable: 0,
+			closed: None,
+			senders: 1,
+			receiving: true,
+			started: false,
+			stats: QueueStats::default()
+		}),
+		notify: Notify::new(),
+		size: size.max(1),
+		policy
+	});
+	(PacketSender { queue: queue.clone() }, PacketReceiver { queue })
+}
+
+pub struct PacketSender
+{
+	queue: Arc<PacketQueue>
+}
+impl PacketSender
+{
+	pub fn send(&self, packet: Packet) -> Result<(), SendError<Packet>>
+	{
+		let mut state = self.queue.state.lock().unwrap();
+		if !state.receiving || state.closed.is_some()
+		{
+			return Err(SendError(packet));
+		}
+		let entity = match &packet
+		{
+			Packet::SetPosAndLook { id, .. } | Packet::UpdatePosAndLook { id, .. } | Packet::UpdatePos { id, .. } | Packet::UpdateLook { id, .. } => Some(*id),
+			Packet::Spawn { id, .. } | Packet::ExtAddEntity2 { id, .. } | Packet::Despawn { id } =>
+			{
+				state.moves.remove(id);
+				None
+			}
+			_ => None
+		};
+		if let Some(entity) = entity
+		{
+			if let Some(index) = state.moves.get(&entity).and_then(|seq| seq.checked_sub(state.popped))
+			{
+				let index = index as usize;
+				if let Some(merged) = merge_movement(&state.packets[index], &packet)
+				{
+					state.packets[index] = merged;
+					state.stats.coalesced += 1;
+					return Ok(());
+				}
+			}
+		}
+		// disconnecting always goes through, or a stuck client could never be kicked.
+		// while joining nothing gets sent yet, so whatever happens in the meantime has to be kept
+		if state.started && state.packets.len() >= self.queue.size && !matches!(packet, Packet::Disconnect { .. })
+		{
+			match self.queue.policy
+			{
+				QueuePolicy::Drop =>
+				{
+					state.stats.dropped += 1;
+					return Ok(());
+				}
+				QueuePolicy::Coalesce if is_droppable(&packet) =>
+				{
+					state.stats.dropped += 1;
+					return Ok(());
+				}
+				// make room by throwing out the oldest movement packet instead
+				QueuePolicy::Coalesce if state.droppable > 0 =>
+				{
+					let index = state.packets.iter().position(is_droppable).unwrap();
+					state.packets.remove(index);
+					state.droppable -= 1;
+					state.stats.dropped += 1;
+					// sequence numbers after the removed packet moved back by one
+					let removed = state.popped + index as u64;
+					state.moves.retain(|_, seq| *seq != removed);
+					for seq in state.moves.values_mut()
+					{
+						if *seq > removed
+						{
+							*seq -= 1;
+						}
+					}
+				}
+				// nothing left that can be lost, so the client has fallen too far behind
+				QueuePolicy::Coalesce | QueuePolicy::Kick =>
+				{
+					drop(state);
+					self.close("Too slow to keep up with the server.".to_string());
+					return Err(SendError(packet));
+				}
+			}
+		}
+		if let Some(entity) = entity
+		{
+			let seq = state.popped + state.packets.len() as u64;
+			state.moves.insert(entity, seq);
+		}
+		if is_droppable(&packet)
+		{
+			state.droppable += 1;
+		}
+		state.packets.push_back(packet);
+		state.stats.queued = state.packets.len();
+		state.stats.peak = state.stats.peak.max(state.packets.len());
+		drop(state);
+		self.queue.notify.notify_one();
+		Ok(())
+	}
+	// disconnects the client before anything still queued, and nothing more gets queued after
+	pub fn close(&self, reason: String)
+	{
+		let mut state = self.queue.state.lock().unwrap();
+		if state.closed.is_none()
+		{
+			state.closed = Some(reason);
+		}
+		drop(state);
+		self.queue.notify.notify_one();
+	}
+	// how many more packets fit before the queue is full
+	pub fn room(&self) -> usize
+	{
+		self.queue.size.saturating_sub(self.queue.state.lock().unwrap().packets.len())
+	}
+	pub fn is_closed(&self) -> bool
+	{
+		self.queue.state.lock().unwrap().closed.is_some()
+	}
+	pub fn stats(&self) -> QueueStats
+	{
+		self.queue.state.lock().unwrap().stats
+	}
+}
+impl Clone for PacketSender
+{
+	fn clone(&self) -> Self
+	{
+		self.queue.state.lock().unwrap().senders += 1;
+		PacketSender { queue: self.queue.clone() }
+	}
+}
+impl Drop for PacketSender
+{
+	fn drop(&mut self)
+	{
+		self.queue.state.lock().unwrap().senders -= 1;
+		self.queue.notify.notify_one();
+	}
+}
+
+pub struct PacketReceiver
+{
+	queue: Arc<PacketQueue>
+}
+impl PacketReceiver
+{
+	// returns None once every sender is gone and nothing is left to send
+	pub async fn recv(&mut self) -> Option<Packet>
+	{
+		loop
+		{
+			{
+				let mut state = self.queue.state.lock().unwrap();
+				state.started = true;
+				if let Some(reason) = &state.closed
+				{
+					return Some(Packet::Disconnect { reason: reason.clone() });
+				}
+				if let Some(packet) = state.packets.pop_front()
+				{
+					if is_droppable(&packet)
+					{
+						state.droppable -= 1;
+					}
+					state.popped += 1;
+					state.stats.sent += 1;
+					state.stats.queued = state.packets.len();
+					return Some(packet);
+				}
+				if state.senders == 0
+				{
+					return None;
+				}
+			}
+			self.queue.notify.notified().await;
+		}
+	}
+}
+impl Drop for PacketReceiver
+{
+	fn drop(&mut self)
+	{
+		self.queue.state.lock().unwrap().receiving = false;
+	}
+}
+
+fn is_droppable(packet: &Packet) -> bool
+{
+	matches!(packet, Packet::SetPosAndLook { .. } | Packet::UpdatePosAndLook { .. } | Packet::UpdatePos { .. } | Packet::UpdateLook { .. } | Packet::Ping)
+}
+enum Move
+{
+	Absolute(i32, i32, i32),
+	Relative(i32, i32, i32)
+}
+struct Movement
+{
+	id: i8,
+	pos: Move,
+	look: Option<(u8, u8)>
+}
+fn split_movement(packet: &Packet) -> Option<Movement>
+{
+	match *packet
+	{
+		Packet::SetPosAndLook { id, x, y, z, yaw, pitch } => Some(Movement { id, pos: Move::Absolute(x, y, z), look: Some((yaw, pitch)) }),
+		Packet::UpdatePosAndLook { id, x, y, z, yaw, pitch } => Some(Movement { id, pos: Move::Relative(x as i32, y as i32, z as i32), look: Some((yaw, pitch)) }),
+		Packet::UpdatePos { id, x, y, z } => Some(Movement { id, pos: Move::Relative(x as i32, y as i32, z as i32), look: None }),
+		Packet::UpdateLook { id, yaw, pitch } => Some(Movement { id, pos: Move::Relative(0, 0, 0), look: Some((yaw, pitch)) }),
+		_ => None
+	}
+}
+// turns two movement packets of the same entity into one, if the result still fits in a packet
+fn merge_movement(old: &Packet, new: &Packet) -> Option<Packet>
+{
+	let old = split_movement(old)?;
+	let new = split_movement(new)?;
+	let (id, look) = (old.id, new.look.or(old.look));
+	match (old.pos, new.pos)
+	{
+		(_, Move::Absolute(x, y, z)) =>
+		{
+			let (yaw, pitch) = look?;
+			Some(Packet::SetPosAndLook { id, x, y, z, yaw, pitch })
+		}
+		(Move::Absolute(x, y, z), Move::Relative(dx, dy, dz)) =>
+		{
+			let (yaw, pitch) = look?;
+			Some(Packet::SetPosAndLook { id, x: x.checked_add(dx)?, y: y.checked_add(dy)?, z: z.checked_add(dz)?, yaw, pitch })
+		}
+		(Move::Relative(ax, ay, az), Move::Relative(bx, by, bz)) =>
+		{
+			let (x, y, z) = (i8::try_from(ax + bx).ok()?, i8::try_from(ay + by).ok()?, i8::try_from(az + bz).ok()?);
+			Some(match look
+			{
+				Some((yaw, pitch)) if x == 0 && y == 0 && z == 0 => Packet::UpdateLook { id, yaw, pitch },
+				Some((yaw, pitch)) => Packet::UpdatePosAndLook { id, x, y, z, yaw, pitch },
+				None => Packet::UpdatePos { id, x, y, z }
+			})
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn pos(id: i8, x: i32) -> Packet
+	{
+		Packet::SetPosAndLook { id, x, y: 0, z: 0, yaw: 0, pitch: 0 }
+	}
+	fn step(id: i8, x: i8) -> Packet
+	{
+		Packet::UpdatePos { id, x, y: 0, z: 0 }
+	}
+	fn message(text: &str) -> Packet
+	{
+		Packet::Message { id: 0, message: text.to_string() }
+	}
+	// a queue whose client is done joining, so its size limit applies
+	async fn started(size: usize) -> (PacketSender, PacketReceiver)
+	{
+		let (sender, mut receiver) = channel(size, QueuePolicy::Coalesce);
+		sender.send(Packet::Ping).unwrap();
+		receiver.recv().await;
+		(sender, receiver)
+	}
+	async fn drain(receiver: &mut PacketReceiver, count: usize) -> Vec<Packet>
+	{
+		let mut packets = Vec::new();
+		for _ in 0..count
+		{
+			packets.push(receiver.recv().await.unwrap());
+		}
+		packets
+	}
+
+	#[tokio::test]
+	async fn coalesces_movement_of_the_same_entity()
+	{
+		let (sender, mut receiver) = channel(16, QueuePolicy::Coalesce);
+		sender.send(pos(1, 100)).unwrap();
+		sender.send(step(2, 5)).unwrap();
+		sender.send(step(1, 3)).unwrap();
+		sender.send(step(2, -2)).unwrap();
+		assert_eq!(sender.stats().coalesced, 2);
+		assert_eq!(sender.stats().queued, 2);
+		let packets = drain(&mut receiver, 2).await;
+		assert!(matches!(packets[0], Packet::SetPosAndLook { id: 1, x: 103, .. }));
+		assert!(matches!(packets[1], Packet::UpdatePos { id: 2, x: 3, .. }));
+	}
+
+	#[tokio::test]
+	async fn does_not_merge_into_packets_already_sent()
+	{
+		let (sender, mut receiver) = channel(16, QueuePolicy::Coalesce);
+		sender.send(step(1, 1)).unwrap();
+		drain(&mut receiver, 1).await;
+		sender.send(message("a")).unwrap();
+		sender.send(step(1, 2)).unwrap();
+		sender.send(step(1, 3)).unwrap();
+		assert_eq!(sender.stats().coalesced, 1);
+		let packets = drain(&mut receiver, 2).await;
+		assert!(matches!(packets[0], Packet::Message { .. }));
+		assert!(matches!(packets[1], Packet::UpdatePos { id: 1, x: 5, .. }));
+	}
+
+	#[tokio::test]
+	async fn keeps_order_around_other_packets()
+	{
+		let (sender, mut receiver) = channel(16, QueuePolicy::Coalesce);
+		sender.send(step(1, 1)).unwrap();
+		sender.send(Packet::Despawn { id: 1 }).unwrap();
+		sender.send(step(1, 2)).unwrap();
+		// a movement packet is never moved past a despawn of its entity
+		assert_eq!(sender.stats().coalesced, 0);
+		let packets = drain(&mut receiver, 3).await;
+		assert!(matches!(packets[0], Packet::UpdatePos { id: 1, x: 1, .. }));
+		assert!(matches!(packets[1], Packet::Despawn { id: 1 }));
+		assert!(matches!(packets[2], Packet::UpdatePos { id: 1, x: 2, .. }));
+	}
+
+	#[tokio::test]
+	async fn evicts_movement_to_make_room()
+	{
+		let (sender, mut receiver) = started(3).await;
+		sender.send(message("a")).unwrap();
+		sender.send(step(1, 1)).unwrap();
+		sender.send(step(2, 1)).unwrap();
+		sender.send(message("b")).unwrap();
+		assert_eq!(sender.stats().dropped, 1);
+		// entity 2 moved back by one, later movement still merges into it
+		sender.send(step(2, 4)).unwrap();
+		assert_eq!(sender.stats().coalesced, 1);
+		// entity 1 has nothing queued anymore, so this one is new and gets dropped
+		sender.send(step(1, 7)).unwrap();
+		assert_eq!(sender.stats().dropped, 2);
+		let packets = drain(&mut receiver, 3).await;
+		assert!(matches!(&packets[0], Packet::Message { message, .. } if message == "a"));
+		assert!(matches!(packets[1], Packet::UpdatePos { id: 2, x: 5, .. }));
+		assert!(matches!(&packets[2], Packet::Message { message, .. } if message == "b"));
+	}
+
+	#[tokio::test]
+	async fn kicks_instead_of_dropping_other_packets()
+	{
+		let (sender, mut receiver) = started(2).await;
+		sender.send(message("a")).unwrap();
+		sender.send(message("b")).unwrap();
+		assert!(sender.send(message("c")).is_err());
+		assert!(matches!(receiver.recv().await, Some(Packet::Disconnect { .. })));
+	}
+
+	#[tokio::test]
+	async fn keeps_everything_while_joining()
+	{
+		let (sender, mut receiver) = channel(2, QueuePolicy::Coalesce);
+		for text in ["a", "b", "c"]
+		{
+			sender.send(message(text)).unwrap();
+		}
+		assert_eq!(sender.room(), 0);
+		let packets = drain(&mut receiver, 3).await;
+		assert!(matches!(&packets[2], Packet::Message { message, .. } if message == "c"));
+		assert_eq!(sender.room(), 2);
+	}
+
+	#[tokio::test]
+	async fn closing_skips_what_is_queued()
+	{
+		let (sender, mut receiver) = channel(16, QueuePolicy::Coalesce);
+		sender.send(message("a")).unwrap();
+		sender.close("Kicked".to_string());
+		sender.close("Kicked again".to_string());
+		assert!(sender.is_closed());
+		assert!(sender.send(message("b")).is_err());
+		assert!(matches!(receiver.recv().await, Some(Packet::Disconnect { reason }) if reason == "Kicked"));
+	}
+}
@@ -1,76 +1,173 @@
+use chrono::Local;
 use crate::block;
 use crate::chat;
+use crate::chat::MessageType;
+use crate::chat::SpamCheck;
+use crate::chat::SpamFilter;
 use crate::client::Client;
 use crate::client::ClientMode;
 use crate::command::CommandList;
 use crate::config::Config;
+use crate::cpe;
+use crate::cpe::Extensions;
+use crate::game::Job;
+use crate::hacks::Hacks;
 use crate::level::Level;
+use crate::level::NetworkCache;
 use crate::packet::Packet;
+use crate::profile::Position;
+use crate::profile::ProfileStore;
+use crate::queue::PacketSender;
+use crate::userdata::EntryInfo;
 use rand::Rng;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
+
+const TELEPORT_GRACE: Duration = Duration::from_secs(1); // how long a client may keep sending positions from before a teleport
+const ALERT_INTERVAL: Duration = Duration::from_secs(10); // between operator alerts about the same player
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
 use tokio::time;
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum PlayerListGroup
+{
+	World,
+	Rank
+}
+
 pub struct Server
 {
 	pub config: Config,
 	pub client_count: i8,
 	pub clients: HashMap<i8, Client>,
 	pub commands: CommandList,
+	pub jobs: VecDeque<Box<dyn Job>>,
 	pub level: Level,
+	pub network_cache: NetworkCache,
+	pub profiles: ProfileStore,
+	pub movement_sent_at: Instant,
+	pub far_movement_sent_at: Instant,
+	pub resynced_at: Instant,
 	pub running: bool,
-	pub salt: String
+	pub salts: HashMap<String, String> // keyed by heartbeat url
 }
 impl Server
 {
+	// announces the server to every server list. the lock is only held to gather what gets sent.
 	pub async fn heartbeat(server: Arc<Mutex<Self>>)
 	{
 		let mut interval = time::interval(Duration::from_secs(45));
-		let mut running_first_time = true;
+		let http = match reqwest::Client::builder().timeout(Duration::from_secs(10)).build()
+		{
+			Ok(http) => http,
+			Err(e) =>
+			{
+				println!("could not start heartbeats: {}", e);
+				return;
+			}
+		};
+		let mut play_urls: HashMap<String, String> = HashMap::new();
 		interval.tick().await;
 		loop
 		{
+			let heartbeats =
 			{
-				let server = server.lock().await;
+				let mut server = server.lock().await;
+				if !server.running
+				{
+					break;
+				}
+				let mut heartbeats = Vec::new();
 				if server.config.heartbeat
 				{
-					let heartbeat_address = server.config.heartbeat_address.clone();
-					let name = urlencoding::encode(&server.config.name.clone()).into_owned();
-					let max_clients = server.config.max_clients;
-					let client_count = server.client_count;
-					let public = server.config.public;
-					let salt = server.salt.clone();
-					let port = server.config.address.port();
-					if let Ok(body) = reqwest::get(format!("{}?port={}&max={}&name={}&public={}&version=7&salt={}&users={}&software=chipscraft\r\n", heartbeat_address, port, max_clients, name, if public { "True" } else { "False" }, salt, client_count)).await
+					let query = vec!
+					[
+						("port", server.config.address.port().to_string()),
+						("max", server.config.max_clients.to_string()),
+						("name", server.config.name.clone()),
+						("public", if server.config.public { "True" } else { "False" }.to_string()),
+						("version", "7".to_string()),
+						("users", server.client_count.to_string()),
+						("software", cpe::APP_NAME.to_string())
+					];
+					for url in server.config.heartbeat_urls.clone()
+					{
+						let mut query = query.clone();
+						query.push(("salt", server.salt(&url)));
+						heartbeats.push((url, query));
+					}
+				}
+				heartbeats
+			};
+			for (url, query) in heartbeats
+			{
+				match Server::send_heartbeat(&http, &url, &query).await
+				{
+					Ok(play_url) =>
 					{
-						if running_first_time
+						if play_urls.get(&url) != Some(&play_url)
 						{
-							if let Ok(text) = body.text().await
-							{
-								println!("heartbeat response: {}", text);
-								running_first_time = false;
-							}
+							println!("heartbeat to {} succeeded, the server can be joined at {}", url, play_url);
+							play_urls.insert(url, play_url);
 						}
 					}
+					Err(e) =>
+					{
+						println!("heartbeat to {} failed: {}", url, e);
+						play_urls.remove(&url);
+					}
 				}
 			}
 			interval.tick().await;
-			if !server.lock().await.running
-			{
-				break;
-			}
 		}
 	}
-	pub async fn start_ticks(server: &Arc<Mutex<Self>>)
+	// returns the url of the server's page on the list
+	async fn send_heartbeat(http: &reqwest::Client, url: &str, query: &[(&str, String)]) -> Result<String, String>
 	{
-		tokio::spawn(Server::heartbeat(server.clone()));
+		let response = http.get(url).query(query).send().await.map_err(|e| e.without_url().to_string())?;
+		let status = response.status();
+		let text = response.text().await.map_err(|e| e.to_string())?;
+		let text = text.trim();
+		// lists report problems as json like {"errors": [["Invalid name"]], "status": "fail"}
+		if let Ok(json) = serde_json::from_str::<serde_json::Value>(text)
+		{
+			let errors: Vec<String> = json["errors"].as_array().into_iter().flatten()
+				.flat_map(|error| error.as_array().cloned().unwrap_or_else(|| vec![error.clone()]))
+				.map(|error| error.as_str().map_or_else(|| error.to_string(), |error| error.to_string()))
+				.collect();
+			if !errors.is_empty()
+			{
+				return Err(errors.join(", "));
+			}
+			if let Some(play_url) = json["response"].as_str().filter(|url| !url.is_empty())
+			{
+				return Ok(play_url.to_string());
+			}
+		}
+		if !status.is_success()
+		{
+			return Err(if text.is_empty() { status.to_string() } else { format!("{} {}", status, text) });
+		}
+		if text.starts_with("http")
+		{
+			Ok(text.to_string())
+		}
+		else
+		{
+			Err(format!("unexpected response: {}", text))
+		}
 	}
-	pub fn new(config: Config, level: Level) -> Self
+	// every server list gets its own salt, so one list cannot make up names that another list verified
+	pub fn salt(&mut self, url: &str) -> String
 	{
 		const BASE62: [char; 62] = [
 			'0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
@@ -81,21 +178,127 @@ impl Server
 			'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x',
 			'y', 'z',
 		]; // stupid strings
-		let mut rng = rand::thread_rng();
-		let mut salt = String::new();
-		for _ in 0..16
+		self.salts.entry(url.to_string()).or_insert_with(||
+		{
+			let mut rng = rand::thread_rng();
+			(0..16).map(|_| BASE62[rng.gen_range(0..62)]).collect()
+		}).clone()
+	}
+	// whether the key a player joined with was made from the salt of one of the server lists
+	pub fn verify_name(&self, name: &str, key: &str) -> bool
+	{
+		self.config.heartbeat_urls.iter().filter_map(|url| self.salts.get(url)).any(|salt| key == format!("{:?}", md5::compute(format!("{}{}", salt, name))))
+	}
+	// takes care of temporary entries in the user lists running out
+	pub async fn expire_user_data(server: Arc<Mutex<Self>>)
+	{
+		let mut interval = time::interval(Duration::from_secs(10));
+		loop
 		{
-			salt.push(BASE62[rng.gen_range(0..62)]);
+			interval.tick().await;
+			let mut server = server.lock().await;
+			if !server.running
+			{
+				break;
+			}
+			for username in server.config.user_data.banned.remove_expired()
+			{
+				println!("ban on {} expired", username);
+			}
+			for username in server.config.user_data.muted.remove_expired()
+			{
+				println!("mute on {} expired", username);
+				if let Some(id) = server.get_index_from_username(&username)
+				{
+					server.send_message(-1, id, "You are no longer muted.");
+				}
+			}
+		}
+	}
+	pub async fn save_profiles(server: Arc<Mutex<Self>>)
+	{
+		let mut interval = time::interval(Duration::from_secs(60));
+		interval.tick().await;
+		loop
+		{
+			interval.tick().await;
+			let data =
+			{
+				let mut server = server.lock().await;
+				if !server.running
+				{
+					break;
+				}
+				server.config.user_data.identities.save_changes();
+				server.profiles.take_save()
+			};
+			let Some(data) = data else { continue; };
+			// gzipping tens of thousands of profiles should not hold up the game
+			let result = tokio::task::spawn_blocking(move || ProfileStore::write(data)).await.map_err(|e| e.to_string()).and_then(|result| result);
+			if let Err(e) = result
+			{
+				println!("{}", e);
+				server.lock().await.profiles.changed = true;
+			}
 		}
+	}
+	pub async fn check_afk(server: Arc<Mutex<Self>>)
+	{
+		let mut interval = time::interval(Duration::from_secs(1));
+		loop
+		{
+			interval.tick().await;
+			let mut server = server.lock().await;
+			if !server.running
+			{
+				break;
+			}
+			let afk_time = Duration::from_secs(server.config.afk_time);
+			let afk_kick_time = Duration::from_secs(server.config.afk_kick_time);
+			for id in 0..server.config.max_clients
+			{
+				if let Some(client) = server.clients.get(&id).filter(|client| !client.packet_sender.is_closed())
+				{
+					let idle = client.last_activity.elapsed();
+					if !afk_kick_time.is_zero() && idle >= afk_kick_time && client.mode != ClientMode::Operator
+					{
+						let username = client.username.clone();
+						println!("{}:{} was kicked for being idle", id, username);
+						server.kick(id, "You have been kicked for being idle.".to_string());
+						server.broadcast_system_message(-1, &format!("{} has been kicked for being idle.", username));
+					}
+					else if !afk_time.is_zero() && idle >= afk_time && !client.afk
+					{
+						server.set_afk(id, "");
+					}
+				}
+			}
+		}
+	}
+	pub async fn start_ticks(server: &Arc<Mutex<Self>>)
+	{
+		tokio::spawn(Server::check_afk(server.clone()));
+		tokio::spawn(Server::heartbeat(server.clone()));
+		tokio::spawn(Server::expire_user_data(server.clone()));
+		tokio::spawn(Server::save_profiles(server.clone()));
+	}
+	pub fn new(config: Config, level: Level) -> Self
+	{
 		Self
 		{
 			config,
 			client_count: 0,
 			clients: HashMap::new(),
 			commands: CommandList::new(),
+			jobs: VecDeque::new(),
+			movement_sent_at: Instant::now(),
+			far_movement_sent_at: Instant::now(),
+			resynced_at: Instant::now(),
 			level,
+			network_cache: NetworkCache::default(),
+			profiles: ProfileStore::load(),
 			running: true,
-			salt
+			salts: HashMap::new()
 		}
 	}
 	pub fn first_free_space(&self) -> Option<i8>
@@ -139,25 +342,72 @@ impl Server
 		}
 		None
 	}
-	pub fn disconnected(&mut self, id: i8)
+	pub fn get_username(&self, id: i8) -> String
 	{
-		let username = if let Some(client) = self.clients.get(&id)
+		if id < 0
+		{
+			"Console".to_string()
+		}
+		else if let Some(client) = self.clients.get(&id)
 		{
 			client.username.clone()
 		}
 		else
+		{
+			"Unknown".to_string()
+		}
+	}
+	// returns the message to disconnect with if this user is banned
+	pub fn ban_message(&self, username: &String, ip: &IpAddr) -> Option<String>
+	{
+		let banned = &self.config.user_data.banned;
+		if !banned.contains(username, ip)
+		{
+			return None;
+		}
+		Some(match banned.get(username, ip).map(|info| info.describe())
+		{
+			Some(describe) if !describe.is_empty() => format!("Banned{}", describe),
+			_ => "You are banned.".to_string()
+		})
+	}
+	pub fn is_whitelisted(&self, username: &String, ip: &IpAddr) -> bool
+	{
+		!self.config.whitelist_enabled || self.config.user_data.whitelist.contains(username, ip)
+	}
+	pub fn disconnected(&mut self, id: i8)
+	{
+		let name = if let Some(client) = self.clients.get(&id)
+		{
+			if client.mode.keeps_profile()
+			{
+				self.profiles.logout(&client.username, &self.level.name, Position { x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch });
+			}
+			self.display_name(id)
+		}
+		else
 		{
 			return;
 		};
 		self.client_count -= 1;
 		self.clients.remove(&id);
+		self.jobs.retain(|job| job.owner() != id);
 		self.broadcast_packet(id, Packet::Despawn { id: id });
-		self.broadcast_system_message(id, &format!("{} left", username));
+		self.broadcast_packet(id, Packet::ExtRemovePlayerName { name_id: id as i16 });
+		self.broadcast_system_message(id, &format!("{}&e left", name));
 	}
+	// takes effect right away, nothing else gets sent to them and the game ignores what they still send.
+	// the player is only removed once their connection is closed.
 	pub fn kick(&mut self, id: i8, reason: String)
 	{
-		let _ = self.send_packet(id, Packet::Disconnect { reason });
-		self.disconnected(id);
+		if let Some(client) = self.clients.get(&id)
+		{
+			client.packet_sender.close(reason);
+		}
+	}
+	pub fn is_kicked(&self, id: i8) -> bool
+	{
+		self.clients.get(&id).is_some_and(|client| client.packet_sender.is_closed())
 	}
 	pub fn send_packet(&mut self, cid: i8, packet: Packet) -> Result<(), SendError<Packet>>
 	{
@@ -167,8 +417,22 @@ impl Server
 		}
 		if let Some(client) = self.clients.get(&cid)
 		{
+			let player_list = client.extensions.has(cpe::EXT_PLAYER_LIST);
 			let r = client.packet_sender.send(match packet
 			{
+				Packet::Spawn { id, name, x, y, z, yaw, pitch } if player_list =>
+				{
+					// the name might be a nickname, skins always go by username
+					let skin = self.clients.get(&id).map_or_else(|| name.clone(), |client| client.username.clone());
+					Packet::ExtAddEntity2 { id: if id == cid {-1} else {id}, name, skin, x, y, z, yaw, pitch }
+				}
+				Packet::ExtAddPlayerName { .. } | Packet::ExtRemovePlayerName { .. } if !player_list => return Ok(()),
+				Packet::EnvSetColor { .. } if !client.extensions.has(cpe::ENV_COLORS) => return Ok(()),
+				Packet::EnvSetWeatherType { .. } if !client.extensions.has(cpe::ENV_WEATHER_TYPE) => return Ok(()),
+				Packet::HackControl { .. } if !client.extensions.has(cpe::HACK_CONTROL) => return Ok(()),
+				Packet::SetMapEnvUrl { .. } | Packet::SetMapEnvProperty { .. } if !client.extensions.has(cpe::ENV_MAP_ASPECT) => return Ok(()),
+				// with MessageTypes the id picks where the line goes, so plain chat has to be 0
+				Packet::Message { message, .. } if client.extensions.has(cpe::MESSAGE_TYPES) => Packet::Message { id: 0, message },
 				Packet::Spawn { id, name, x, y, z, yaw, pitch } => Packet::Spawn { id: if id == cid {-1} else {id}, name, x, y, z, yaw, pitch},
 				Packet::SetPosAndLook { id, x, y, z, yaw, pitch } => Packet::SetPosAndLook { id: if id == cid {-1} else {id}, x, y, z, yaw, pitch },
 				Packet::UpdatePosAndLook { id, x, y, z, yaw, pitch } => Packet::UpdatePosAndLook { id: if id == cid {-1} else {id}, x, y, z, yaw, pitch },
@@ -176,33 +440,136 @@ impl Server
 				Packet::UpdateLook { id , yaw, pitch } => Packet::UpdateLook { id: if id == cid {-1} else {id}, yaw, pitch },
 				_ => packet
 			});
-			if r.is_err()
-			{
-				self.disconnected(cid);
-			}
 			return r;
 		}
 		Err(SendError(packet))
 	}
+	// how a player shows up in the tab list of clients with ExtPlayerList
+	fn player_list_entry(&self, id: i8) -> Option<Packet>
+	{
+		let client = self.clients.get(&id)?;
+		let operator = client.mode == ClientMode::Operator;
+		let (group_name, group_rank) = match self.config.player_list_group
+		{
+			PlayerListGroup::World => (self.level.name.clone(), 0),
+			PlayerListGroup::Rank => match client.mode
+			{
+				ClientMode::Operator => ("Operators".to_string(), 0),
+				ClientMode::Normal => ("Players".to_string(), 1),
+				ClientMode::Guest => ("Guests".to_string(), 2)
+			}
+		};
+		let (name, color) = self.appearance(&client.username);
+		Some(Packet::ExtAddPlayerName
+		{
+			name_id: id as i16,
+			player_name: client.username.clone(),
+			list_name: format!("&{}{}", color.unwrap_or(if operator { 'c' } else { 'f' }), name),
+			group_name,
+			group_rank
+		})
+	}
+	// the nickname and colour a player picked, the nickname falling back to their username
+	pub fn appearance(&self, username: &str) -> (String, Option<char>)
+	{
+		if self.get_client_from_username(username).is_some_and(|client| !client.mode.keeps_profile())
+		{
+			return (username.to_string(), Some('7'));
+		}
+		match self.profiles.get(username)
+		{
+			Some(profile) => (if profile.nick.is_empty() { username.to_string() } else { profile.nick.clone() }, profile.color),
+			None => (username.to_string(), None)
+		}
+	}
+	// what a player is called in chat and in the world, with their colour
+	pub fn display_name(&self, id: i8) -> String
+	{
+		let username = self.get_username(id);
+		match self.appearance(&username)
+		{
+			(name, Some(color)) => format!("&{}{}", color, name),
+			(name, None) => name
+		}
+	}
+	// shows everyone a player's new nickname, colour or title
+	pub fn update_appearance(&mut self, id: i8)
+	{
+		let spawn = match self.clients.get(&id)
+		{
+			Some(client) => Packet::Spawn { id, name: self.display_name(id), x: client.sent.x, y: client.sent.y, z: client.sent.z, yaw: client.sent.yaw, pitch: client.sent.pitch },
+			None => return
+		};
+		for cid in 0..self.config.max_clients
+		{
+			if cid != id && self.clients.contains_key(&cid)
+			{
+				let _ = self.send_packet(cid, Packet::Despawn { id });
+				let _ = self.send_packet(cid, spawn.clone());
+			}
+		}
+		self.update_player_list(id);
+	}
+	// sends everyone the current tab list entry of a player
+	pub fn update_player_list(&mut self, id: i8)
+	{
+		if let Some(entry) = self.player_list_entry(id)
+		{
+			self.broadcast_packet(-1, entry);
+		}
+	}
+	// after the environment of the level changed
+	pub fn update_env(&mut self)
+	{
+		self.level.changed = true;
+		for packet in self.level.env.packets(self.level.size_y)
+		{
+			self.broadcast_packet(-1, packet);
+		}
+	}
+	// the hacks of the level, unless the player's rank lets them do anything
+	pub fn send_hacks(&mut self, id: i8)
+	{
+		let exempt = match self.clients.get(&id)
+		{
+			Some(client) => client.mode >= self.config.hacks_exempt_rank,
+			None => return
+		};
+		let packet = if exempt { Hacks::default().packet() } else { self.level.hacks.packet() };
+		let _ = self.send_packet(id, packet);
+	}
 	pub fn set_block(&mut self, id: i8, x: i16, y: i16, z: i16, block: u8, aware: bool)
 	{
 		let mut place_block = false;
-		if x >= 0 && y >= 0 && z >= 0 && x < self.level.size_x && y < self.level.size_y && z < self.level.size_z
+		let in_level = x >= 0 && y >= 0 && z >= 0 && x < self.level.size_x && y < self.level.size_y && z < self.level.size_z;
+		if in_level
 		{
 			if (block as usize) < block::BLOCKS.len()
 			{
-				let (mode, restricted) = if id < 0 { (ClientMode::Operator, false) } else if let Some(client) = self.clients.get(&id) { (client.mode, self.config.user_data.restricted.contains(&client.username, &client.ip.ip())) } else { (ClientMode::Normal, true) };
+				let (mode, restricted) = if id < 0 { (ClientMode::Operator, false) } else if let Some(client) = self.clients.get(&id) { (client.mode, client.mode == ClientMode::Guest || self.config.user_data.restricted.contains(&client.username, &client.ip.ip())) } else { (ClientMode::Normal, true) };
 				let placed_block = &block::BLOCKS[block as usize];
 				let replaced_block = &block::BLOCKS[self.level.get_block(x, y, z) as usize];
 				if ((!placed_block.place_op_only && !replaced_block.destroy_op_only) || mode == ClientMode::Operator) && !restricted
 				{
-					place_block = true;
+					place_block = !aware || self.check_block_change(id, x, y, z);
 				}
 			}
 		}
 		let mut should_discard_original_placed_block = true;
 		if place_block
 		{
+			if let Some(client) = self.clients.get(&id).filter(|client| client.mode.keeps_profile())
+			{
+				let profile = self.profiles.get_mut(&client.username);
+				if block == 0
+				{
+					profile.blocks_destroyed += 1;
+				}
+				else
+				{
+					profile.blocks_placed += 1;
+				}
+			}
 			for (xx,yy,zz,bblock) in self.level.place_block(x, y, z, block)
 			{
 				if should_discard_original_placed_block && xx == x && yy == y && zz == z
@@ -212,65 +579,212 @@ impl Server
 				self.broadcast_packet(-1, Packet::SetBlock { x:xx, y:yy, z:zz, block:bblock });
 			}
 		}
-		if should_discard_original_placed_block && aware
+		// there is nothing to revert outside the level
+		if should_discard_original_placed_block && aware && in_level
 		{
 			let _ = self.send_packet(id, Packet::SetBlock { x, y, z, block: self.level.get_block(x, y, z) });
 		}
 	}
-	pub fn move_player(&mut self, to_move: i8, mover: i8, x: i16, y: i16, z: i16, yaw: u8, pitch: u8)
+	// whether the middle of a player's body or their eyes are in a block
+	fn inside_block(&self, x: i32, y: i32, z: i32) -> bool
+	{
+		let (block_x, block_z) = (x.div_euclid(32), z.div_euclid(32));
+		[y.saturating_sub(22), y].into_iter().map(|y| y.div_euclid(32)).any(|block_y|
+			block_x >= 0 && block_y >= 0 && block_z >= 0 && block_x < self.level.size_x as i32 && block_y < self.level.size_y as i32 && block_z < self.level.size_z as i32
+			&& block::BLOCKS.get(self.level.get_block(block_x as i16, block_y as i16, block_z as i16) as usize).is_some_and(|block| block.solid))
+	}
+	// checks a position sent by a player against how far they could have moved and where they can be.
+	// players who fail are sent back to their last accepted position.
+	fn check_movement(&mut self, id: i8, x: i32, y: i32, z: i32, yaw: u8, pitch: u8) -> bool
 	{
-		let (position_changed, rotation_changed, x_diff, y_diff, z_diff);
-		if let Some(client) = self.clients.get_mut(&to_move)
+		let Some(client) = self.clients.get(&id) else { return false; };
+		if client.mode >= self.config.hacks_exempt_rank
 		{
-			position_changed = client.x != x || client.y != y || client.z != z;
-			if position_changed
-			{
-				// we do not want 
-				x_diff = if let (Ok(x), Ok(client_x)) = (i8::try_from(x), i8::try_from(client.x)) {x.checked_sub(client_x)} else { None };
-				y_diff = if let (Ok(y), Ok(client_y)) = (i8::try_from(y), i8::try_from(client.y)) {y.checked_sub(client_y)} else { None };
-				z_diff = if let (Ok(z), Ok(client_z)) = (i8::try_from(z), i8::try_from(client.z)) {z.checked_sub(client_z)} else { None };
-				client.x = x;
-				client.y = y;
-				client.z = z;
-			}
-			else
-			{
-				x_diff = Some(0);
-				y_diff = Some(0);
-				z_diff = Some(0);
-			}
-			rotation_changed = client.yaw != yaw || client.pitch != pitch;
-			if rotation_changed
+			return true;
+		}
+		let hacks = &self.level.hacks;
+		let distance = ((x as f32 - client.x as f32).powi(2) + (y as f32 - client.y as f32).powi(2) + (z as f32 - client.z as f32).powi(2)).sqrt() / 32.0;
+		let limit = if hacks.speeding { self.config.max_speeding_move_distance } else { self.config.max_move_distance };
+		let respawned = hacks.spawn_control && (x - self.level.spawn_x).abs() < 32 && (y - self.level.spawn_y).abs() < 32 && (z - self.level.spawn_z).abs() < 32;
+		let violation = if limit > 0.0 && distance > limit && !respawned
+		{
+			format!("moved {:.1} blocks at once", distance)
+		}
+		else if !hacks.noclip && self.inside_block(x, y, z)
+		{
+			"was inside a block".to_string()
+		}
+		else
+		{
+			if let Some(client) = self.clients.get_mut(&id)
 			{
-				client.yaw = yaw;
-				client.pitch = pitch;
+				client.teleported = None;
 			}
+			return true;
+		};
+		let Some(client) = self.clients.get_mut(&id) else { return false; };
+		if client.teleported.is_some_and(|time| time.elapsed() < TELEPORT_GRACE)
+		{
+			// positions sent before the client knew about the teleport
+			return false;
+		}
+		client.teleported = Some(Instant::now());
+		let packet = Packet::SetPosAndLook { id, x: client.x, y: client.y, z: client.z, yaw, pitch };
+		let _ = self.send_packet(id, packet);
+		self.violation(id, &format!("{}, sent back", violation));
+		false
+	}
+	// rejects block changes out of a player's reach or faster than anyone could click
+	fn check_block_change(&mut self, id: i8, x: i16, y: i16, z: i16) -> bool
+	{
+		let (max_reach, max_blocks) = (self.config.max_reach, self.config.max_blocks_per_second);
+		let Some(client) = self.clients.get_mut(&id) else { return false; };
+		if client.mode >= self.config.hacks_exempt_rank
+		{
+			return true;
+		}
+		let distance = ((x as f32 * 32.0 + 16.0 - client.x as f32).powi(2) + (y as f32 * 32.0 + 16.0 - client.y as f32).powi(2) + (z as f32 * 32.0 + 16.0 - client.z as f32).powi(2)).sqrt() / 32.0;
+		let now = Instant::now();
+		while client.block_times.front().is_some_and(|time| now.duration_since(*time) > Duration::from_secs(1))
+		{
+			client.block_times.pop_front();
+		}
+		let violation = if max_reach > 0.0 && distance > max_reach
+		{
+			format!("changed a block {:.1} blocks away", distance)
+		}
+		else if max_blocks > 0 && client.block_times.len() >= max_blocks
+		{
+			format!("changed more than {} blocks in a second", max_blocks)
 		}
 		else
+		{
+			client.block_times.push_back(now);
+			return true;
+		};
+		self.violation(id, &format!("{}, reverted", violation));
+		false
+	}
+	// counts a rejected movement or block change, and lets operators know every now and then
+	fn violation(&mut self, id: i8, violation: &str)
+	{
+		let Some(client) = self.clients.get_mut(&id) else { return; };
+		client.violations += 1;
+		let message = format!("{} {} (violation #{})", client.username, violation, client.violations);
+		if client.alerted_at.is_some_and(|time| time.elapsed() < ALERT_INTERVAL)
+		{
+			println!("{}", message);
+		}
+		else
+		{
+			client.alerted_at = Some(Instant::now());
+			self.broadcast_operator_message(&message);
+		}
+	}
+	pub fn move_player(&mut self, to_move: i8, mover: i8, x: i32, y: i32, z: i32, yaw: u8, pitch: u8)
+	{
+		if to_move == mover && !self.check_movement(to_move, x, y, z, yaw, pitch)
 		{
 			return;
 		}
-		if position_changed
+		let Some(client) = self.clients.get_mut(&to_move) else { return; };
+		if to_move != mover
+		{
+			client.teleported = Some(Instant::now());
+		}
+		let position_changed = client.x != x || client.y != y || client.z != z;
+		(client.x, client.y, client.z, client.yaw, client.pitch) = (x, y, z, yaw, pitch);
+		if position_changed && to_move == mover
 		{
-			if x_diff.is_none() || y_diff.is_none() || z_diff.is_none()
+			self.activity(to_move);
+		}
+		// what players do themselves waits for the next movement tick, teleports are sent right away
+		if to_move == mover && self.config.movement_interval > 0
+		{
+			if let Some(client) = self.clients.get_mut(&to_move)
 			{
-				self.broadcast_packet(mover, Packet::SetPosAndLook { id: to_move, x, y, z, yaw, pitch });
+				client.moved = true;
 			}
-			else
+			return;
+		}
+		self.send_movement(to_move, mover, true);
+	}
+	// tells everyone how a player moved since they were last told, using the smallest packet that fits.
+	// far away players can be skipped for now, they get the full position once they are due again.
+	fn send_movement(&mut self, id: i8, mover: i8, far_due: bool)
+	{
+		let Some(client) = self.clients.get_mut(&id) else { return; };
+		client.moved = false;
+		let (old, new) = (client.sent, Position { x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch });
+		let changed = old != new;
+		client.sent = new;
+		let absolute = Packet::SetPosAndLook { id, x: new.x, y: new.y, z: new.z, yaw: new.yaw, pitch: new.pitch };
+		// relative packets work anywhere in the level, as long as the player did not move too far at once
+		let diff = |new: i32, old: i32| new.checked_sub(old).and_then(|diff| i8::try_from(diff).ok());
+		let rotation_changed = old.yaw != new.yaw || old.pitch != new.pitch;
+		let relative = match (diff(new.x, old.x), diff(new.y, old.y), diff(new.z, old.z))
+		{
+			(Some(0), Some(0), Some(0)) => Packet::UpdateLook { id, yaw: new.yaw, pitch: new.pitch },
+			(Some(x), Some(y), Some(z)) if rotation_changed => Packet::UpdatePosAndLook { id, x, y, z, yaw: new.yaw, pitch: new.pitch },
+			(Some(x), Some(y), Some(z)) => Packet::UpdatePos { id, x, y, z },
+			_ => absolute.clone()
+		};
+		let far_distance = self.config.far_movement_distance * 32.0;
+		for cid in 0..self.config.max_clients
+		{
+			if cid == mover
 			{
-				if rotation_changed
-				{
-					self.broadcast_packet(mover, Packet::UpdatePosAndLook { id: to_move, x: x_diff.unwrap(), y: y_diff.unwrap(), z: z_diff.unwrap(), yaw, pitch });
-				}
-				else
+				continue;
+			}
+			let Some(receiver) = self.clients.get_mut(&cid) else { continue; };
+			let distance = ((receiver.x as f32 - new.x as f32).powi(2) + (receiver.y as f32 - new.y as f32).powi(2) + (receiver.z as f32 - new.z as f32).powi(2)).sqrt();
+			let packet = if cid != id && far_distance > 0.0 && distance > far_distance && !far_due
+			{
+				if changed
 				{
-					self.broadcast_packet(mover, Packet::UpdatePos { id: to_move, x: x_diff.unwrap(), y: y_diff.unwrap(), z: z_diff.unwrap() });
+					receiver.stale.insert(id);
 				}
+				continue;
+			}
+			else if receiver.stale.remove(&id)
+			{
+				absolute.clone()
 			}
+			else if !changed
+			{
+				continue;
+			}
+			else if cid == id
+			{
+				absolute.clone()
+			}
+			else
+			{
+				relative.clone()
+			};
+			let _ = self.send_packet(cid, packet);
 		}
-		else if rotation_changed
+	}
+	// sends what every player did since the last movement tick
+	pub fn broadcast_movement(&mut self)
+	{
+		let interval = Duration::from_millis(self.config.movement_interval);
+		if interval.is_zero() || self.movement_sent_at.elapsed() < interval
+		{
+			return;
+		}
+		self.movement_sent_at = Instant::now();
+		let far_due = self.far_movement_sent_at.elapsed() >= Duration::from_millis(self.config.far_movement_interval);
+		if far_due
 		{
-			self.broadcast_packet(mover, Packet::UpdateLook { id: to_move, yaw, pitch });
+			self.far_movement_sent_at = Instant::now();
+		}
+		// players who stopped moving still owe far away players their last position
+		let stale: HashSet<i8> = if far_due { self.clients.values().flat_map(|client| client.stale.iter().copied()).collect() } else { HashSet::new() };
+		let moved: Vec<i8> = self.clients.iter().filter(|(id, client)| client.moved || stale.contains(id)).map(|(id, _)| *id).collect();
+		for id in moved
+		{
+			self.send_movement(id, id, far_due);
 		}
 	}
 	pub fn broadcast_packet(&mut self, oid: i8, packet: Packet)
@@ -321,6 +835,98 @@ impl Server
 			let _ = self.send_packet(toid, Packet::Message { id, message: line.to_string() }); // fuck man
 		}
 	}
+	// these lines replace whatever was there before, and an empty message clears them.
+	// clients without MessageTypes only get announcements, in their chat.
+	pub fn send_message_type(&mut self, toid: i8, message_type: MessageType, message: &str)
+	{
+		let supported = self.clients.get(&toid).is_some_and(|client| client.extensions.has(cpe::MESSAGE_TYPES));
+		if message_type == MessageType::Chat || (!supported && message_type == MessageType::Announcement)
+		{
+			self.send_message(-1, toid, message);
+		}
+		else if supported
+		{
+			// skips send_packet, which would turn this back into chat
+			let line = chat::wrap_and_clean(message, 'e').into_iter().next().unwrap_or_default();
+			if let Some(client) = self.clients.get(&toid)
+			{
+				let _ = client.packet_sender.send(Packet::Message { id: message_type.get_id(), message: line });
+			}
+		}
+	}
+	pub fn broadcast_message_type(&mut self, message_type: MessageType, message: &str)
+	{
+		println!("({}) {}", message_type.get_id(), message);
+		for cid in 0..self.config.max_clients
+		{
+			if self.clients.contains_key(&cid)
+			{
+				self.send_message_type(cid, message_type, message);
+			}
+		}
+	}
+	// anything that shows the player is still there
+	pub fn activity(&mut self, id: i8)
+	{
+		if let Some(client) = self.clients.get_mut(&id)
+		{
+			client.last_activity = Instant::now();
+			if client.afk
+			{
+				client.afk = false;
+				let name = self.display_name(id);
+				self.broadcast_system_message(-1, &format!("{}&e is no longer AFK.", name));
+			}
+		}
+	}
+	pub fn set_afk(&mut self, id: i8, reason: &str)
+	{
+		if let Some(client) = self.clients.get_mut(&id)
+		{
+			if !client.afk
+			{
+				client.afk = true;
+				let name = self.display_name(id);
+				if reason.is_empty()
+				{
+					self.broadcast_system_message(-1, &format!("{}&e is now AFK.", name));
+				}
+				else
+				{
+					self.broadcast_system_message(-1, &format!("{}&e is now AFK: {}", name, reason));
+				}
+			}
+		}
+	}
+	pub fn broadcast_operator_message(&mut self, message: &str)
+	{
+		println!("(ops) {}", message);
+		for cid in 0..self.config.max_clients
+		{
+			if self.clients.get(&cid).is_some_and(|client| client.mode == ClientMode::Operator)
+			{
+				for line in chat::wrap_and_clean(message, 'e')
+				{
+					let _ = self.send_packet(cid, Packet::Message { id: -1, message: line });
+				}
+			}
+		}
+	}
+	// lets operators know when someone shares an ip with a banned account
+	pub fn flag_alts(&mut self, id: i8)
+	{
+		if !self.config.flag_banned_alts
+		{
+			return;
+		}
+		let username = self.get_username(id);
+		let user_data = &self.config.user_data;
+		let banned: Vec<String> = user_data.identities.alts(&username).into_iter().filter(|alt| user_data.banned.contains_username(alt)).collect();
+		if !banned.is_empty()
+		{
+			self.broadcast_operator_message(&format!("&c{} shares an ip with banned player(s): {}", username, banned.join(", ")));
+		}
+	}
 	pub fn command(&mut self, id: i8, name: String, args: Vec<&str>)
 	{
 		let (username, mode, muted, restricted): (&str, ClientMode, bool, bool) = if id == -1 { ("Console", ClientMode::Operator, false, false) } else 
@@ -335,6 +941,7 @@ impl Server
 			}
 		};
 		println!("{}:{} is running command /{} {}", id, username, name, args.join(" "));
+		self.activity(id);
 		if let Some(command) = self.commands.get(&name)
 		{
 			if command.ops_only && mode != ClientMode::Operator
@@ -349,6 +956,10 @@ impl Server
 			{
 				self.send_message(-1, id, "You are restricted, you cannot use this command.");
 			}
+			else if command.unrestricted_only && mode == ClientMode::Guest
+			{
+				self.send_message(-1, id, "Guests cannot use this command.");
+			}
 			else if let Err(err) = (command.run)(self, id, args, mode)
 			{
 				self.send_message(-1, id, &err);
@@ -359,24 +970,169 @@ impl Server
 			self.send_message(-1, id, "Unknown command. See /help.");
 		}
 	}
-	pub fn spawn(&mut self, id: i8, ip: SocketAddr, username: String, mode: ClientMode, packet_sender: UnboundedSender<Packet>) -> Result<(), SendError<Packet>>
+	pub fn chat(&mut self, id: i8, message: String)
+	{
+		let (username, ip) = match self.clients.get(&id)
+		{
+			Some(client) => (client.username.clone(), client.ip.ip()),
+			None => return
+		};
+		if self.config.user_data.muted.contains(&username, &ip)
+		{
+			println!("{}:<{}> (muted) {}", id, username, message);
+			let describe = self.config.user_data.muted.get(&username, &ip).map(|info| info.describe()).unwrap_or_default();
+			self.send_message(-1, id, &format!("You have been muted{}, you cannot send any messages.", describe));
+			return;
+		}
+		let check = match self.clients.get_mut(&id)
+		{
+			Some(client) => client.spam_filter.check(&message, &self.config),
+			None => return
+		};
+		match check
+		{
+			SpamCheck::Allowed =>
+			{
+				self.activity(id);
+				let keeps_profile = self.clients.get(&id).is_some_and(|client| client.mode.keeps_profile());
+				if keeps_profile
+				{
+					self.profiles.get_mut(&username).messages_sent += 1;
+				}
+				let title = self.profiles.get(&username).filter(|_| keeps_profile).map(|profile| profile.title.clone()).unwrap_or_default();
+				let title = if title.is_empty() { title } else { format!("&7[{}&7] ", title) };
+				let name = self.display_name(id);
+				let message = self.format_chat(id, &message);
+				self.broadcast_message(id, &format!("{}<{}&f> {}", title, name, message));
+			}
+			SpamCheck::Warned =>
+			{
+				println!("{}:<{}> (spam) {}", id, username, message);
+				self.send_message(-1, id, "&cYou are sending messages too quickly. Slow down or you will be muted.");
+			}
+			SpamCheck::Muted =>
+			{
+				let info = EntryInfo::new("Spamming".to_string(), "Spam filter".to_string(), Some(self.config.spam_mute_duration));
+				println!("{}:{} was muted by the spam filter{}", id, username, info.describe());
+				self.send_message(-1, id, &format!("&cYou have been muted{}.", info.describe()));
+				self.config.user_data.muted.add_username_with_info(username, info);
+			}
+		}
+	}
+	// what players type. colour codes are only kept for ranks allowed to use them.
+	pub fn format_chat(&self, id: i8, message: &str) -> String
+	{
+		let colors = id < 0 || self.clients.get(&id).is_some_and(|client| client.mode >= self.config.chat_color_rank);
+		if colors
+		{
+			chat::format(message, &[&chat::percent_codes, &chat::emotes])
+		}
+		else
+		{
+			chat::format(message, &[&chat::percent_codes, &chat::strip_colors, &chat::emotes])
+		}
+	}
+	// text from the config like the motd and the rules, which can also use $tokens
+	pub fn format_text(&self, text: &str, username: &str) -> String
+	{
+		let values =
+		[
+			("name", username.to_string()),
+			("world", self.level.name.clone()),
+			("time", Local::now().format("%H:%M").to_string()),
+			("online", self.client_count.to_string()),
+			("server", self.config.name.clone())
+		];
+		chat::format(text, &[&|text| chat::tokens(text, &values), &chat::percent_codes, &chat::emotes])
+	}
+	// relative movement drifts when a packet gets dropped from a full queue, so everyone's actual position is sent now and then
+	pub fn resync_positions(&mut self)
+	{
+		let interval = Duration::from_secs(self.config.position_resync_interval);
+		if interval.is_zero() || self.resynced_at.elapsed() < interval
+		{
+			return;
+		}
+		self.resynced_at = Instant::now();
+		let mut positions = Vec::new();
+		for (id, client) in self.clients.iter_mut()
+		{
+			client.moved = false;
+			client.sent = Position { x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch };
+			client.stale.clear();
+			positions.push((*id, Packet::SetPosAndLook { id: *id, x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch }));
+		}
+		for (id, packet) in positions
+		{
+			self.broadcast_packet(id, packet);
+		}
+	}
+	// gives the oldest job its share of this tick
+	pub fn run_jobs(&mut self, budget: usize)
+	{
+		// every changed block goes to every player, so the fullest queue decides how much gets done.
+		// half of its room is left for chat and movement
+		let room = self.clients.values().map(|client| client.packet_sender.room()).min().unwrap_or(budget);
+		let budget = budget.min(room / 2);
+		if budget == 0
+		{
+			return;
+		}
+		if let Some(mut job) = self.jobs.pop_front()
+		{
+			if !job.run(self, budget)
+			{
+				self.jobs.push_front(job);
+			}
+		}
+	}
+	pub fn spawn(&mut self, id: i8, ip: SocketAddr, username: String, mode: ClientMode, extensions: Extensions, packet_sender: PacketSender) -> Result<(), SendError<Packet>>
 	{
 		let x = self.level.spawn_x;
 		let y = self.level.spawn_y;
 		let z = self.level.spawn_z;
 		let yaw = self.level.spawn_yaw;
 		let pitch = self.level.spawn_pitch;
-		for i in 0..self.config.max_clients
+		let others: Vec<i8> = self.clients.keys().copied().collect();
+		self.client_count += 1;
+		if mode.keeps_profile()
+		{
+			self.profiles.login(&username);
+		}
+		self.clients.insert(id, Client { ip, username: username.clone(), packet_sender, x, y, z, yaw, pitch, mode, extensions, last_activity: Instant::now(), afk: false, spam_filter: SpamFilter::default(), violations: 0, alerted_at: None, teleported: None, block_times: VecDeque::new(), sent: Position { x, y, z, yaw, pitch }, moved: false, stale: HashSet::new() } );
+		// what the new player gets depends on the extensions they have, so it all goes through send_packet
+		for i in others
 		{
 			if let Some(client) = self.clients.get(&i)
 			{
-				packet_sender.send(Packet::Spawn { id: i, name: client.username.clone(), x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch })?;
+				// the position everyone else knows, so the movement sent next applies to the new player too
+				let spawn = Packet::Spawn { id: i, name: self.display_name(i), x: client.sent.x, y: client.sent.y, z: client.sent.z, yaw: client.sent.yaw, pitch: client.sent.pitch };
+				self.send_packet(id, spawn)?;
+			}
+			if let Some(entry) = self.player_list_entry(i)
+			{
+				self.send_packet(id, entry)?;
 			}
 		}
-		self.client_count += 1;
-		self.clients.insert(id, Client { ip, username: username.clone(), packet_sender, x, y, z, yaw, pitch, mode } );
-		self.broadcast_system_message(id, &format!("{} joined", username.clone()));
-		self.broadcast_packet(id, Packet::Spawn { id: id, name: username, x, y, z, yaw, pitch});
+		let name = self.display_name(id);
+		if mode == ClientMode::Guest
+		{
+			self.broadcast_system_message(id, &format!("{}&e joined as a guest", name));
+			self.send_message(-1, id, "&eYou could not be verified, so you joined as a guest and cannot build.");
+		}
+		else
+		{
+			self.broadcast_system_message(id, &format!("{}&e joined", name));
+		}
+		self.broadcast_packet(id, Packet::Spawn { id: id, name, x, y, z, yaw, pitch});
+		self.update_player_list(id);
+		let world = format!("&eWorld: {}", self.level.name);
+		self.send_message_type(id, MessageType::Status1, &world);
+		for packet in self.level.env.packets(self.level.size_y)
+		{
+			self.send_packet(id, packet)?;
+		}
+		self.send_hacks(id);
 		Ok(())
 	}
 	pub async fn stop(&mut self)
@@ -384,15 +1140,33 @@ impl Server
 		println!("shutting down...");
 
 		self.running = false;
-		self.broadcast_message(-1, "&cShutting down server in three seconds.");
-		tokio::time::sleep(Duration::from_secs(3)).await;
-		self.broadcast_packet(-1, Packet::Disconnect { reason: "Stopping server".to_string() });
-		tokio::time::sleep(Duration::from_secs(1)).await;
-
-		if self.level.save().is_err()
+		for seconds in (1..=3).rev()
+		{
+			self.broadcast_message_type(MessageType::Announcement, &format!("&cShutting down server in {}...", seconds));
+			tokio::time::sleep(Duration::from_secs(1)).await;
+		}
+		// players stay connected while saving, so they know their changes are kept
+		self.broadcast_message_type(MessageType::Status2, "&eSaving world...");
+		let saved = match self.level.take_save()
+		{
+			Some(level) => tokio::task::spawn_blocking(move || level.write()).await.map_err(|e| e.to_string()).and_then(|result| result),
+			None => Ok(())
+		};
+		if saved.is_err()
 		{
 			println!("could not save.");
 		}
+		for client in self.clients.values().filter(|client| client.mode.keeps_profile())
+		{
+			self.profiles.logout(&client.username, &self.level.name, Position { x: client.x, y: client.y, z: client.z, yaw: client.yaw, pitch: client.pitch });
+		}
+		if let Err(e) = self.profiles.save()
+		{
+			println!("{}", e);
+		}
+		self.config.user_data.identities.save_changes();
+		self.broadcast_packet(-1, Packet::Disconnect { reason: "Stopping server".to_string() });
+		tokio::time::sleep(Duration::from_secs(1)).await;
 	}
 	pub fn reload_config(&mut self) -> Result<(), String>
 	{
@@ -401,20 +1175,76 @@ impl Server
 			Err(e) => Err(e),
 			Ok(config) =>
 			{
+				self.config = config;
 				for id in 0..self.config.max_clients
 				{
 					if let Some(client) = self.clients.get(&id)
 					{
-						if config.user_data.banned.contains(&client.username, &client.ip.ip())
+						if let Some(reason) = self.ban_message(&client.username, &client.ip.ip())
 						{
-							self.kick(id, "You have been banned.".to_string());
+							self.kick(id, reason);
 						}
 					}
+					// the grouping might have changed
+					self.update_player_list(id);
 				}
-				self.config = config;
 				println!("reloaded config file");
 				Ok(())
 			}
 		}
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use tokio::io::AsyncReadExt;
+	use tokio::io::AsyncWriteExt;
+	use tokio::net::TcpListener;
+
+	// a server list that answers a single request with the given status and body
+	async fn list(status: &'static str, body: &'static str) -> String
+	{
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		tokio::spawn(async move
+		{
+			let (mut stream, _) = listener.accept().await.unwrap();
+			let mut request = Vec::new();
+			let mut buf = [0; 1024];
+			while !request.ends_with(b"\r\n\r\n")
+			{
+				let n = stream.read(&mut buf).await.unwrap();
+				if n == 0
+				{
+					break;
+				}
+				request.extend_from_slice(&buf[..n]);
+			}
+			let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+			stream.write_all(response.as_bytes()).await.unwrap();
+		});
+		format!("http://{}/server/heartbeat", address)
+	}
+	async fn heartbeat(status: &'static str, body: &'static str) -> Result<String, String>
+	{
+		let url = list(status, body).await;
+		Server::send_heartbeat(&reqwest::Client::new(), &url, &[("salt", "secret".to_string())]).await
+	}
+
+	#[tokio::test]
+	async fn heartbeat_returns_the_play_url()
+	{
+		assert_eq!(heartbeat("200 OK", "http://www.classicube.net/server/play/abc\n").await, Ok("http://www.classicube.net/server/play/abc".to_string()));
+		assert_eq!(heartbeat("200 OK", r#"{"response": "http://list/play/abc", "status": "success"}"#).await, Ok("http://list/play/abc".to_string()));
+	}
+
+	#[tokio::test]
+	async fn heartbeat_reports_errors()
+	{
+		assert_eq!(heartbeat("200 OK", r#"{"errors": [["Invalid name"], ["Port out of range"]], "status": "fail"}"#).await, Err("Invalid name, Port out of range".to_string()));
+		assert_eq!(heartbeat("500 Internal Server Error", "oops").await, Err("500 Internal Server Error oops".to_string()));
+		assert_eq!(heartbeat("404 Not Found", "").await, Err("404 Not Found".to_string()));
+		assert_eq!(heartbeat("200 OK", "not a url").await, Err("unexpected response: not a url".to_string()));
+	}
+}
@@ -1,14 +1,132 @@
+use chrono::Local;
+use chrono::TimeZone;
+use chrono::Utc;
+use crate::iprange::IpIndex;
+use crate::iprange::IpRange;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::Write;
 use std::net::IpAddr;
 
+// parses durations such as "30s", "10m", "2h", "7d" or "1h30m" into seconds
+pub fn parse_duration(duration: &str) -> Option<i64>
+{
+	let mut total = 0i64;
+	let mut number = String::new();
+	for c in duration.chars()
+	{
+		if c.is_ascii_digit()
+		{
+			number.push(c);
+			continue;
+		}
+		let unit = match c.to_ascii_lowercase()
+		{
+			's' => 1,
+			'm' => 60,
+			'h' => 60 * 60,
+			'd' => 60 * 60 * 24,
+			'w' => 60 * 60 * 24 * 7,
+			_ => return None
+		};
+		total = total.checked_add(number.parse::<i64>().ok()?.checked_mul(unit)?)?;
+		number.clear();
+	}
+	if !number.is_empty()
+	{
+		// a bare number is taken as minutes
+		total = total.checked_add(number.parse::<i64>().ok()?.checked_mul(60)?)?;
+	}
+	if total > 0 { Some(total) } else { None }
+}
+pub fn format_duration(mut seconds: i64) -> String
+{
+	let mut string = String::new();
+	for (unit, name) in [(60 * 60 * 24, 'd'), (60 * 60, 'h'), (60, 'm')]
+	{
+		if seconds >= unit
+		{
+			string.push_str(&format!("{}{}", seconds / unit, name));
+			seconds %= unit;
+		}
+	}
+	if seconds > 0 || string.is_empty()
+	{
+		string.push_str(&format!("{}s", seconds));
+	}
+	string
+}
+
+pub fn format_time(timestamp: i64) -> String
+{
+	match Local.timestamp_opt(timestamp, 0).single()
+	{
+		Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+		None => "never".to_string()
+	}
+}
+
+// extra information about why and until when someone is in a list
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct EntryInfo
+{
+	#[serde(default)]
+	pub reason: String,
+	#[serde(default)]
+	pub by: String,
+	#[serde(default)]
+	pub time: i64,
+	#[serde(default)]
+	pub expires: Option<i64>
+}
+impl EntryInfo
+{
+	pub fn new(reason: String, by: String, duration: Option<i64>) -> Self
+	{
+		let time = Utc::now().timestamp();
+		EntryInfo { reason, by, time, expires: duration.map(|d| time + d) }
+	}
+	pub fn expired(&self) -> bool
+	{
+		self.expires.is_some_and(|expires| expires <= Utc::now().timestamp())
+	}
+	pub fn remaining(&self) -> Option<i64>
+	{
+		self.expires.map(|expires| (expires - Utc::now().timestamp()).max(0))
+	}
+	// e.g. " for 1h59m: griefing"
+	pub fn describe(&self) -> String
+	{
+		let mut string = String::new();
+		if let Some(remaining) = self.remaining()
+		{
+			string.push_str(&format!(" for {}", format_duration(remaining)));
+		}
+		if !self.reason.is_empty()
+		{
+			string.push_str(&format!(": {}", self.reason));
+		}
+		string
+	}
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct UserList
 {
 	usernames: Vec<String>,
 	ips: Vec<IpAddr>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	ranges: Vec<IpRange>,
+	// keyed by username, ip or range
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	info: HashMap<String, EntryInfo>,
+	#[serde(skip)]
+	index: IpIndex,
+	#[serde(skip)]
+	range_expiry: HashMap<IpRange, i64>, // copied out of info, so looking up an address does not format every range
 	#[serde(skip)]
 	file: String
 }
@@ -32,9 +150,31 @@ impl UserList
 			UserList::default()
 		};
 		list.file = file.to_string();
-		list.save();
+		// ipv4 addresses of players connecting over ipv6 used to be stored as ::ffff:a.b.c.d
+		for ip in list.ips.iter_mut()
+		{
+			let canonical = ip.to_canonical();
+			if canonical != *ip
+			{
+				if let Some(info) = list.info.remove(&ip.to_string())
+				{
+					list.info.insert(canonical.to_string(), info);
+				}
+				*ip = canonical;
+			}
+		}
+		let mut seen = HashSet::new();
+		list.ips.retain(|ip| seen.insert(*ip));
+		list.update();
 		list
 	}
+	// the index has to be rebuilt every time the addresses change
+	fn update(&mut self)
+	{
+		self.index = IpIndex::new(self.ips.iter(), self.ranges.iter());
+		self.range_expiry = self.ranges.iter().filter_map(|range| Some((*range, self.info.get(&range.to_string())?.expires?))).collect();
+		self.save();
+	}
 	pub fn save(&self)
 	{
 		if let (Ok(json), Ok(mut file)) = (serde_json::to_string_pretty(self), File::create(&self.file))
@@ -49,41 +189,163 @@ impl UserList
 			println!("could not create {}.", self.file);
 		}
 	}
+	pub fn usernames(&self) -> &Vec<String>
+	{
+		&self.usernames
+	}
+	pub fn ips(&self) -> &Vec<IpAddr>
+	{
+		&self.ips
+	}
+	pub fn ranges(&self) -> &Vec<IpRange>
+	{
+		&self.ranges
+	}
+	fn is_expired(&self, key: &str) -> bool
+	{
+		self.info.get(key).is_some_and(|info| info.expired())
+	}
 	pub fn contains_username(&self, username: &String) -> bool
 	{
-		self.usernames.contains(username)
+		self.usernames.contains(username) && !self.is_expired(username)
+	}
+	fn get_range(&self, ip: &IpAddr) -> Option<&IpRange>
+	{
+		let now = Utc::now().timestamp();
+		self.index.ranges(ip).into_iter().map(|i| &self.ranges[i]).find(|range| self.range_expiry.get(range).is_none_or(|expires| *expires > now))
 	}
 	pub fn contains_ip(&self, ip: &IpAddr) -> bool
 	{
-		self.ips.contains(ip)
+		let ip = &ip.to_canonical();
+		// the index is only there to quickly rule out addresses that are not in the list at all
+		self.index.contains(ip) && ((self.ips.contains(ip) && !self.is_expired(&ip.to_string())) || self.get_range(ip).is_some())
+	}
+	pub fn contains_range(&self, range: &IpRange) -> bool
+	{
+		self.ranges.contains(range) && !self.is_expired(&range.to_string())
 	}
 	// do double check
 	pub fn contains(&self, username: &String, ip: &IpAddr) -> bool
 	{
 		self.contains_ip(ip) || self.contains_username(username)
 	}
+	pub fn get_username(&self, username: &String) -> Option<&EntryInfo>
+	{
+		if self.contains_username(username) { self.info.get(username) } else { None }
+	}
+	pub fn get_ip(&self, ip: &IpAddr) -> Option<&EntryInfo>
+	{
+		let ip = &ip.to_canonical();
+		if !self.contains_ip(ip)
+		{
+			return None;
+		}
+		self.info.get(&ip.to_string()).or_else(|| self.get_range(ip).and_then(|range| self.info.get(&range.to_string())))
+	}
+	// the username entry usually has the more relevant reason, so it goes first
+	pub fn get(&self, username: &String, ip: &IpAddr) -> Option<&EntryInfo>
+	{
+		self.get_username(username).or_else(|| self.get_ip(ip))
+	}
 	pub fn add_username(&mut self, username: String)
 	{
 		if !self.contains_username(&username)
 		{
+			self.info.remove(&username);
+			self.usernames.retain(|i| *i != username);
 			self.usernames.push(username);
-			self.save();
+			self.update();
 		}
 	}
 	pub fn add_ip(&mut self, ip: IpAddr)
 	{
-		if !self.contains_ip(&ip)
+		let ip = ip.to_canonical();
+		if !self.ips.contains(&ip) || self.is_expired(&ip.to_string())
 		{
+			self.info.remove(&ip.to_string());
+			self.ips.retain(|i| *i != ip);
 			self.ips.push(ip);
-			self.save();
+			self.update();
+		}
+	}
+	// adding someone already in the list replaces their info
+	pub fn add_username_with_info(&mut self, username: String, info: EntryInfo)
+	{
+		self.info.insert(username.clone(), info);
+		if !self.usernames.contains(&username)
+		{
+			self.usernames.push(username);
+		}
+		self.update();
+	}
+	pub fn add_ip_with_info(&mut self, ip: IpAddr, info: EntryInfo)
+	{
+		let ip = ip.to_canonical();
+		self.info.insert(ip.to_string(), info);
+		if !self.ips.contains(&ip)
+		{
+			self.ips.push(ip);
+		}
+		self.update();
+	}
+	pub fn add_range_with_info(&mut self, range: IpRange, info: EntryInfo)
+	{
+		self.info.insert(range.to_string(), info);
+		if !self.ranges.contains(&range)
+		{
+			self.ranges.push(range);
+		}
+		self.update();
+	}
+	pub fn add_range(&mut self, range: IpRange)
+	{
+		if !self.contains_range(&range)
+		{
+			self.info.remove(&range.to_string());
+			self.ranges.retain(|i| *i != range);
+			self.ranges.push(range);
+			self.update();
 		}
 	}
+	pub fn remove_range(&mut self, range: &IpRange) -> bool
+	{
+		if self.contains_range(range)
+		{
+			self.ranges.retain(|i| *i != *range);
+			self.info.remove(&range.to_string());
+			self.update();
+			true
+		}
+		else
+		{
+			false
+		}
+	}
+	// removes every entry that ran out, and returns what was removed
+	pub fn remove_expired(&mut self) -> Vec<String>
+	{
+		let expired: Vec<String> = self.info.iter().filter(|(_, info)| info.expired()).map(|(key, _)| key.clone()).collect();
+		if expired.is_empty()
+		{
+			return expired;
+		}
+		for key in &expired
+		{
+			self.info.remove(key);
+			self.usernames.retain(|i| i != key);
+			self.ips.retain(|i| i.to_string() != *key);
+			self.ranges.retain(|i| i.to_string() != *key);
+		}
+		self.update();
+		expired
+	}
 	pub fn remove_username(&mut self, username: &String) -> bool
 	{
 		if self.contains_username(username)
 		{
 			self.usernames.retain(|i| *i != *username);
-			self.save();
+			self.info.remove(username);
+			self.update();
 			true
 		}
 		else
@@ -93,10 +355,12 @@ impl UserList
 	}
 	pub fn remove_ip(&mut self, ip: &IpAddr) -> bool
 	{
-		if self.contains_ip(ip)
+		let ip = &ip.to_canonical();
+		if self.ips.contains(ip) && !self.is_expired(&ip.to_string())
 		{
 			self.ips.retain(|i| *i != *ip);
-			self.save();
+			self.info.remove(&ip.to_string());
+			self.update();
 			true
 		}
 		else
@@ -105,12 +369,122 @@ impl UserList
 		}
 	}
 }
+// every name and address a player has been seen with
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Identity
+{
+	pub ips: Vec<IpAddr>,
+	pub first_seen: i64,
+	pub last_seen: i64
+}
+#[derive(Default, Serialize, Deserialize)]
+pub struct IdentityList
+{
+	players: HashMap<String, Identity>,
+	#[serde(skip)]
+	by_ip: HashMap<IpAddr, Vec<String>>,
+	#[serde(skip)]
+	changed: bool, // players join all the time, so the file is only written now and then
+	#[serde(skip)]
+	file: String
+}
+impl IdentityList
+{
+	pub fn load(file: &str) -> IdentityList
+	{
+		let mut list: IdentityList = if let Ok(file) = File::open(file)
+		{
+			serde_json::from_reader(file).unwrap_or_default()
+		}
+		else
+		{
+			IdentityList::default()
+		};
+		for (username, identity) in list.players.iter_mut()
+		{
+			// older versions kept ipv4 addresses of players connecting over ipv6 as ::ffff:a.b.c.d
+			let mut seen = HashSet::new();
+			identity.ips = identity.ips.iter().map(|ip| ip.to_canonical()).filter(|ip| seen.insert(*ip)).collect();
+			for ip in &identity.ips
+			{
+				list.by_ip.entry(*ip).or_default().push(username.clone());
+			}
+		}
+		list.file = file.to_string();
+		list.save();
+		list
+	}
+	pub fn save(&self)
+	{
+		if let (Ok(json), Ok(mut file)) = (serde_json::to_string(self), File::create(&self.file))
+		{
+			if file.write(json.as_bytes()).is_err()
+			{
+				println!("could not write to {}.", self.file);
+			}
+		}
+		else
+		{
+			println!("could not create {}.", self.file);
+		}
+	}
+	pub fn save_changes(&mut self)
+	{
+		if self.changed
+		{
+			self.save();
+			self.changed = false;
+		}
+	}
+	pub fn record(&mut self, username: &str, ip: IpAddr)
+	{
+		let ip = ip.to_canonical();
+		let now = Utc::now().timestamp();
+		let identity = self.players.entry(username.to_string()).or_insert_with(|| Identity { ips: vec![], first_seen: now, last_seen: now });
+		identity.last_seen = now;
+		if !identity.ips.contains(&ip)
+		{
+			identity.ips.push(ip);
+			self.by_ip.entry(ip).or_default().push(username.to_string());
+		}
+		self.changed = true;
+	}
+	pub fn get(&self, username: &String) -> Option<&Identity>
+	{
+		self.players.get(username)
+	}
+	pub fn names_on_ip(&self, ip: &IpAddr) -> &[String]
+	{
+		self.by_ip.get(&ip.to_canonical()).map(|names| names.as_slice()).unwrap_or_default()
+	}
+	// every other account that logged in from one of the same addresses
+	pub fn alts(&self, username: &String) -> Vec<String>
+	{
+		let mut alts: Vec<String> = vec![];
+		if let Some(identity) = self.players.get(username)
+		{
+			for ip in &identity.ips
+			{
+				for name in self.names_on_ip(ip)
+				{
+					if name != username && !alts.contains(name)
+					{
+						alts.push(name.clone());
+					}
+				}
+			}
+		}
+		alts
+	}
+}
 pub struct UserData
 {
 	pub ops: UserList,
 	pub banned: UserList,
 	pub muted: UserList,
-	pub restricted: UserList
+	pub restricted: UserList,
+	pub whitelist: UserList,
+	pub identities: IdentityList
 }
 impl UserData
 {
@@ -118,6 +492,8 @@ impl UserData
 	const BANNED: &str = "banned.json";
 	const MUTED: &str = "muted.json";
 	const RESTRICTED: &str = "restricted.json";
+	const WHITELIST: &str = "whitelist.json";
+	const IDENTITIES: &str = "identities.json";
 	pub fn load() -> UserData
 	{
 		UserData
@@ -125,7 +501,67 @@ impl UserData
 			ops: UserList::load(UserData::OPS),
 			banned: UserList::load(UserData::BANNED),
 			muted: UserList::load(UserData::MUTED),
-			restricted: UserList::load(UserData::RESTRICTED)
+			restricted: UserList::load(UserData::RESTRICTED),
+			whitelist: UserList::load(UserData::WHITELIST),
+			identities: IdentityList::load(UserData::IDENTITIES)
 		}
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn mapped_addresses_are_the_same_player()
+	{
+		let mut identities = IdentityList::default();
+		identities.record("alice", "::ffff:10.0.0.1".parse().unwrap());
+		identities.record("bob", "10.0.0.1".parse().unwrap());
+		assert_eq!(identities.alts(&"alice".to_string()), vec!["bob".to_string()]);
+		assert_eq!(identities.names_on_ip(&"::ffff:10.0.0.1".parse().unwrap()).len(), 2);
+		assert_eq!(identities.get(&"alice".to_string()).unwrap().ips, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+	}
+
+	#[test]
+	fn parses_durations()
+	{
+		assert_eq!(parse_duration("30s"), Some(30));
+		assert_eq!(parse_duration("1h30m"), Some(90 * 60));
+		assert_eq!(parse_duration("2D"), Some(2 * 24 * 60 * 60));
+		assert_eq!(parse_duration("1w1s"), Some(7 * 24 * 60 * 60 + 1));
+		// a bare number is in minutes
+		assert_eq!(parse_duration("15"), Some(15 * 60));
+		assert_eq!(parse_duration("1h5"), Some(65 * 60));
+		assert_eq!(parse_duration("0m"), None);
+		assert_eq!(parse_duration(""), None);
+		assert_eq!(parse_duration("h"), None);
+		assert_eq!(parse_duration("5y"), None);
+		assert_eq!(parse_duration("-5m"), None);
+		assert_eq!(parse_duration("99999999999999999999s"), None);
+		assert_eq!(parse_duration("9223372036854775807w"), None);
+	}
+
+	#[test]
+	fn formats_durations()
+	{
+		assert_eq!(format_duration(0), "0s");
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(60 * 60 + 5), "1h5s");
+		assert_eq!(format_duration(2 * 24 * 60 * 60 + 60), "2d1m");
+	}
+
+	#[test]
+	fn user_lists_match_mapped_addresses_and_expire_ranges()
+	{
+		let mut list = UserList::default();
+		list.add_ip("::ffff:1.2.3.4".parse().unwrap());
+		assert!(list.contains_ip(&"1.2.3.4".parse().unwrap()));
+		assert!(list.remove_ip(&"::ffff:1.2.3.4".parse().unwrap()));
+		assert!(!list.contains_ip(&"1.2.3.4".parse().unwrap()));
+		list.add_range_with_info("10.0.0.0/8".parse().unwrap(), EntryInfo { expires: Some(1), ..Default::default() });
+		list.add_range("10.1.0.0/16".parse().unwrap());
+		assert!(!list.contains_ip(&"10.2.0.1".parse().unwrap()));
+		assert!(list.contains_ip(&"::ffff:10.1.0.1".parse().unwrap()));
+	}
+}